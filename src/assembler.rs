@@ -0,0 +1,718 @@
+use crate::cpu::{ Instruction, MAX_XO_PROGRAM_SIZE, PROGRAM_START_ADDR };
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+const SOURCE_NAME: &str = "<source>";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error<T, M: Into<String>>(&self, message: M) -> Result<T, AssembleError> {
+        Err(AssembleError { file: self.file.clone(), line: self.line, message: message.into() })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+// A sum of terms such as `sprites + 5` or `END - START`.
+#[derive(Clone, Debug, PartialEq)]
+struct Expr {
+    terms: Vec<(bool, Term)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    LargeFont,
+    Bcd,
+    Flags,
+    Value(Expr),
+    // The 16-bit address following `LD I, LONG`.
+    Long(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Instruction { mnemonic: String, operands: Vec<Operand> },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Symbol {
+    Label(usize),
+    Constant(Expr),
+}
+
+struct Assembler {
+    symbols: HashMap<String, (Symbol, Location)>,
+    statements: Vec<(usize, Statement, Location)>,
+    addr: usize,
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {},
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('v') | Some('V') => {},
+        _ => return None,
+    }
+
+    let digit = chars.next()?.to_digit(16)?;
+    if chars.next().is_some() {
+        return None;
+    }
+
+    Some(digit as u8)
+}
+
+fn is_reserved(name: &str) -> bool {
+    parse_register(name).is_some() ||
+        ["I", "DT", "ST", "K", "F", "HF", "B", "R"].iter().any(|word| word.eq_ignore_ascii_case(name))
+}
+
+// Some 0nnn words are other instructions (00E0, 00EE, 00Cn, ...), so SYS
+// cannot reach those addresses.
+pub(crate) fn sys(addr: u16) -> Result<Instruction, String> {
+    match Instruction::decode(addr) {
+        Ok(Instruction::Sys { .. }) => Ok(Instruction::Sys { addr }),
+        Ok(other) => Err(format!("{:#05x} is not a SYS address; it decodes as {}", addr, other)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub(crate) fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = lower.strip_prefix('#') {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn parse_expr(text: &str, location: &Location) -> Result<Expr, AssembleError> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut current = String::new();
+
+    let mut push = |negative: bool, token: &str| -> Result<(), AssembleError> {
+        let token = token.trim();
+        if token.is_empty() {
+            return location.error(format!("Expected a value in `{}`", text));
+        }
+
+        let term = match parse_number(token) {
+            Some(number) => Term::Number(number),
+            None if is_identifier(token) && !is_reserved(token) => Term::Symbol(token.to_string()),
+            None => return location.error(format!("Invalid value `{}`", token)),
+        };
+
+        terms.push((negative, term));
+        Ok(())
+    };
+
+    for c in text.chars() {
+        match c {
+            '+' | '-' if !current.trim().is_empty() => {
+                push(negative, &current)?;
+                current.clear();
+                negative = c == '-';
+            },
+            '-' if current.trim().is_empty() => { negative = !negative; },
+            _ => { current.push(c); },
+        }
+    }
+    push(negative, &current)?;
+
+    Ok(Expr { terms })
+}
+
+fn parse_operand(text: &str, location: &Location) -> Result<Operand, AssembleError> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::LargeFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::Flags,
+        _ if upper.starts_with("LONG ") => Operand::Long(parse_expr(&text[5..], location)?),
+        _ => match parse_register(text) {
+            Some(register) => Operand::Register(register),
+            None => Operand::Value(parse_expr(text, location)?),
+        },
+    };
+
+    Ok(operand)
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return vec![];
+    }
+
+    text.split(',').map(|operand| operand.trim()).collect()
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => { in_string = !in_string; },
+            ';' if !in_string => return &text[..index],
+            _ => {},
+        }
+    }
+
+    text
+}
+
+fn describe(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(x) => format!("V{:X}", x),
+        Operand::I => "I".to_string(),
+        Operand::IndirectI => "[I]".to_string(),
+        Operand::DelayTimer => "DT".to_string(),
+        Operand::SoundTimer => "ST".to_string(),
+        Operand::Key => "K".to_string(),
+        Operand::Font => "F".to_string(),
+        Operand::LargeFont => "HF".to_string(),
+        Operand::Bcd => "B".to_string(),
+        Operand::Flags => "R".to_string(),
+        Operand::Value(_) => "a value".to_string(),
+        Operand::Long(_) => "a long address".to_string(),
+    }
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler { symbols: HashMap::new(), statements: Vec::new(), addr: PROGRAM_START_ADDR }
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, location: &Location) -> Result<(), AssembleError> {
+        if is_reserved(name) {
+            return location.error(format!("`{}` is a reserved name", name));
+        }
+
+        if let Some((_, first)) = self.symbols.get(name) {
+            return location.error(format!("`{}` is already defined at {}", name, first));
+        }
+
+        self.symbols.insert(name.to_string(), (symbol, location.clone()));
+        Ok(())
+    }
+
+    fn read(&mut self, source: &str, file: &str, dir: &Path, includes: &mut Vec<PathBuf>) -> Result<(), AssembleError> {
+        for (index, raw) in source.lines().enumerate() {
+            let location = Location { file: file.to_string(), line: index + 1 };
+            let mut text = strip_comment(raw).trim();
+
+            if let Some(colon) = text.find(':') {
+                let label = text[..colon].trim();
+                if is_identifier(label) {
+                    self.define(label, Symbol::Label(self.addr), &location)?;
+                    text = text[colon + 1..].trim();
+                }
+            }
+
+            if text.is_empty() {
+                continue;
+            }
+
+            let (word, rest) = match text.find(char::is_whitespace) {
+                Some(split) => (&text[..split], text[split..].trim()),
+                None => (text, ""),
+            };
+
+            if let Some(value) = rest.strip_prefix('=') {
+                if !is_identifier(word) {
+                    return location.error(format!("Invalid constant name `{}`", word));
+                }
+
+                let expr = parse_expr(value.trim(), &location)?;
+                self.define(word, Symbol::Constant(expr), &location)?;
+                continue;
+            }
+
+            let mnemonic = word.to_ascii_uppercase();
+            match mnemonic.as_str() {
+                "INCLUDE" => { self.include(rest, dir, includes, &location)?; },
+                "DB" | "DW" => {
+                    let values = split_operands(rest).iter()
+                        .map(|value| parse_expr(value, &location))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    if values.is_empty() {
+                        return location.error(format!("{} needs at least one value", mnemonic));
+                    }
+
+                    let (size, statement) = if mnemonic == "DB" {
+                        (values.len(), Statement::Bytes(values))
+                    } else {
+                        (values.len() * 2, Statement::Words(values))
+                    };
+
+                    self.statements.push((self.addr, statement, location));
+                    self.addr += size;
+                },
+                _ => {
+                    let operands = split_operands(rest).iter()
+                        .map(|operand| parse_operand(operand, &location))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    // Only F000 is followed by an extra word.
+                    let size = if operands.iter().any(|operand| matches!(operand, Operand::Long(_))) { 4 } else { 2 };
+
+                    self.statements.push((self.addr, Statement::Instruction { mnemonic, operands }, location));
+                    self.addr += size;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn include(&mut self, rest: &str, dir: &Path, includes: &mut Vec<PathBuf>, location: &Location) -> Result<(), AssembleError> {
+        let name = match rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+            Some(name) if !name.is_empty() => name,
+            _ => return location.error("INCLUDE expects a quoted file name"),
+        };
+
+        let path = dir.join(name);
+        if includes.contains(&path) {
+            return location.error(format!("`{}` includes itself", name));
+        }
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => return location.error(format!("Could not read `{}`: {}", name, err)),
+        };
+
+        let include_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        includes.push(path.clone());
+        self.read(&source, &path.display().to_string(), &include_dir, includes)?;
+        includes.pop();
+
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str, location: &Location, resolving: &mut Vec<String>) -> Result<i64, AssembleError> {
+        match self.symbols.get(name) {
+            Some((Symbol::Label(addr), _)) => Ok(*addr as i64),
+            Some((Symbol::Constant(_), defined)) if resolving.iter().any(|n| n == name) =>
+                defined.error(format!("`{}` is defined in terms of itself", name)),
+            Some((Symbol::Constant(expr), defined)) => {
+                resolving.push(name.to_string());
+                let value = self.eval_at(expr, defined, resolving)?;
+                resolving.pop();
+
+                Ok(value)
+            },
+            None => location.error(format!("Undefined symbol `{}`", name)),
+        }
+    }
+
+    fn eval_at(&self, expr: &Expr, location: &Location, resolving: &mut Vec<String>) -> Result<i64, AssembleError> {
+        let mut total: i64 = 0;
+        for (negative, term) in expr.terms.iter() {
+            let value = match term {
+                Term::Number(number) => *number,
+                Term::Symbol(name) => self.resolve(name, location, resolving)?,
+            };
+
+            let sum = if *negative { total.checked_sub(value) } else { total.checked_add(value) };
+            total = match sum {
+                Some(sum) => sum,
+                None => return location.error("Value out of range"),
+            };
+        }
+
+        Ok(total)
+    }
+
+    fn eval(&self, expr: &Expr, location: &Location, max: i64, what: &str) -> Result<i64, AssembleError> {
+        let value = self.eval_at(expr, location, &mut Vec::new())?;
+
+        // Negative bytes and words are accepted as their two's complement.
+        let min = if max > 0xFFF { -(max + 1) / 2 } else if max == 0xFF { -128 } else { 0 };
+        if value < min || value > max {
+            return location.error(format!("{} does not fit in {}", value, what));
+        }
+
+        Ok(value & max)
+    }
+
+    fn addr(&self, expr: &Expr, location: &Location) -> Result<u16, AssembleError> {
+        Ok(self.eval(expr, location, 0xFFF, "an address")? as u16)
+    }
+
+    fn byte(&self, expr: &Expr, location: &Location) -> Result<u8, AssembleError> {
+        Ok(self.eval(expr, location, 0xFF, "a byte")? as u8)
+    }
+
+    fn nibble(&self, expr: &Expr, location: &Location) -> Result<u8, AssembleError> {
+        Ok(self.eval(expr, location, 0xF, "a nibble")? as u8)
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand], location: &Location) -> Result<Instruction, AssembleError> {
+        use self::Operand::*;
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => Instruction::Clear,
+            ("RET", []) => Instruction::Return,
+            ("EXIT", []) => Instruction::Exit,
+            ("SCD", [Value(n)]) => Instruction::ScrollDown { n: self.nibble(n, location)? },
+            ("SCU", [Value(n)]) => Instruction::ScrollUp { n: self.nibble(n, location)? },
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("SYS", [Value(addr)]) => sys(self.addr(addr, location)?).or_else(|message| location.error(message))?,
+            ("JP", [Value(addr)]) => Instruction::Jump { addr: self.addr(addr, location)? },
+            ("JP", [Register(0), Value(addr)]) => Instruction::JumpV0 { addr: self.addr(addr, location)? },
+            ("CALL", [Value(addr)]) => Instruction::Call { addr: self.addr(addr, location)? },
+            ("SE", [Register(x), Value(byte)]) => Instruction::SkipIfEqual { x: *x, byte: self.byte(byte, location)? },
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipIfRegistersEqual { x: *x, y: *y },
+            ("SNE", [Register(x), Value(byte)]) => Instruction::SkipIfNotEqual { x: *x, byte: self.byte(byte, location)? },
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipIfRegistersNotEqual { x: *x, y: *y },
+            ("LD", [Register(x), Value(byte)]) => Instruction::Store { x: *x, byte: self.byte(byte, location)? },
+            ("LD", [Register(x), Register(y)]) => Instruction::Copy { x: *x, y: *y },
+            ("LD", [I, Value(addr)]) => Instruction::StoreI { addr: self.addr(addr, location)? },
+            ("LD", [I, Long(_)]) => Instruction::StoreILong,
+            ("LD", [Register(x), DelayTimer]) => Instruction::LoadDelayTimer { x: *x },
+            ("LD", [Register(x), Key]) => Instruction::LoadKeyPress { x: *x },
+            ("LD", [DelayTimer, Register(x)]) => Instruction::SetDelayTimer { x: *x },
+            ("LD", [SoundTimer, Register(x)]) => Instruction::SetSoundTimer { x: *x },
+            ("LD", [Font, Register(x)]) => Instruction::LoadSpriteAddr { x: *x },
+            ("LD", [LargeFont, Register(x)]) => Instruction::LoadLargeSpriteAddr { x: *x },
+            ("LD", [Flags, Register(x)]) => Instruction::StoreFlags { x: *x },
+            ("LD", [Register(x), Flags]) => Instruction::LoadFlags { x: *x },
+            ("LD", [Bcd, Register(x)]) => Instruction::StoreBcd { x: *x },
+            ("LD", [IndirectI, Register(x)]) => Instruction::StoreRegisters { x: *x },
+            ("LD", [Register(x), IndirectI]) => Instruction::LoadRegisters { x: *x },
+            ("ADD", [Register(x), Value(byte)]) => Instruction::AddValue { x: *x, byte: self.byte(byte, location)? },
+            ("ADD", [Register(x), Register(y)]) => Instruction::Add { x: *x, y: *y },
+            ("ADD", [I, Register(x)]) => Instruction::AddToI { x: *x },
+            ("OR", [Register(x), Register(y)]) => Instruction::Or { x: *x, y: *y },
+            ("AND", [Register(x), Register(y)]) => Instruction::And { x: *x, y: *y },
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [Register(x), Register(y)]) => Instruction::Subn { x: *x, y: *y },
+            ("SHR", [Register(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight { x: *x, y: *y },
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft { x: *x, y: *y },
+            ("RND", [Register(x), Value(byte)]) => Instruction::Random { x: *x, byte: self.byte(byte, location)? },
+            ("DRW", [Register(x), Register(y), Value(n)]) => Instruction::Draw { x: *x, y: *y, n: self.nibble(n, location)? },
+            ("SKP", [Register(x)]) => Instruction::SkipIfKeyPressed { x: *x },
+            ("SKNP", [Register(x)]) => Instruction::SkipIfKeyNotPressed { x: *x },
+            ("SAVE", [Register(x), Register(y)]) => Instruction::StoreRange { x: *x, y: *y },
+            ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("PLANE", [Value(n)]) => Instruction::SelectPlanes { n: self.eval(n, location, 0x3, "a plane mask")? as u8 },
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("PITCH", [Register(x)]) => Instruction::SetPitch { x: *x },
+            ("CLS", _) | ("RET", _) | ("EXIT", _) | ("SCD", _) | ("SCR", _) | ("SCL", _) |
+            ("LOW", _) | ("HIGH", _) | ("SYS", _) | ("JP", _) | ("CALL", _) |
+            ("SE", _) | ("SNE", _) | ("LD", _) | ("ADD", _) | ("OR", _) | ("AND", _) |
+            ("XOR", _) | ("SUB", _) | ("SUBN", _) | ("SHR", _) | ("SHL", _) | ("RND", _) |
+            ("DRW", _) | ("SKP", _) | ("SKNP", _) | ("SCU", _) | ("SAVE", _) | ("LOAD", _) |
+            ("PLANE", _) | ("AUDIO", _) | ("PITCH", _) => {
+                let described: Vec<String> = operands.iter().map(describe).collect();
+                return location.error(format!("Invalid operands for {}: {}", mnemonic,
+                    if described.is_empty() { "none".to_string() } else { described.join(", ") }));
+            },
+            _ => return location.error(format!("Unknown instruction `{}`", mnemonic)),
+        };
+
+        Ok(instruction)
+    }
+
+    fn emit(&self) -> Result<Vec<u8>, AssembleError> {
+        let mut program = Vec::with_capacity(self.addr - PROGRAM_START_ADDR);
+
+        for (_, statement, location) in self.statements.iter() {
+            match statement {
+                Statement::Instruction { mnemonic, operands } => {
+                    let opcode = self.instruction(mnemonic, operands, location)?.encode();
+                    program.extend_from_slice(&opcode.to_be_bytes());
+
+                    if let Some(Operand::Long(addr)) = operands.last() {
+                        let word = self.eval(addr, location, 0xFFFF, "a long address")? as u16;
+                        program.extend_from_slice(&word.to_be_bytes());
+                    }
+                },
+                Statement::Bytes(values) => {
+                    for value in values {
+                        program.push(self.byte(value, location)?);
+                    }
+                },
+                Statement::Words(values) => {
+                    for value in values {
+                        let word = self.eval(value, location, 0xFFFF, "a word")? as u16;
+                        program.extend_from_slice(&word.to_be_bytes());
+                    }
+                },
+            }
+        }
+
+        if program.len() > MAX_XO_PROGRAM_SIZE {
+            let location = &self.statements.last().unwrap().2;
+            return location.error(format!("Program is {} bytes but only {} bytes fit in memory",
+                program.len(), MAX_XO_PROGRAM_SIZE));
+        }
+
+        Ok(program)
+    }
+}
+
+fn assemble_in(source: &str, file: &str, dir: &Path, includes: &mut Vec<PathBuf>) -> Result<Vec<u8>, AssembleError> {
+    let mut assembler = Assembler::new();
+    assembler.read(source, file, dir, includes)?;
+    assembler.emit()
+}
+
+/// Assembles `source` into a program image starting at 0x200.
+/// Included files are looked up relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_in(source, SOURCE_NAME, Path::new(""), &mut Vec::new())
+}
+
+/// Assembles the file at `path`. Included files are looked up relative to
+/// the file that includes them.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AssembleError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+
+    let source = fs::read_to_string(path).map_err(|err| AssembleError {
+        file: file.clone(),
+        line: 0,
+        message: format!("Could not read file: {}", err),
+    })?;
+
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    assemble_in(&source, &file, &dir, &mut vec![path.to_path_buf()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn error_message(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_assemble_instructions() {
+        let program = assemble("
+            CLS
+            LD V0, 0x05        ; load five
+            ADD V0, V1
+            SE VA, 10
+            DRW V2, V3, 6
+            LD [I], VF
+            LD V4, [I]
+            SHR V1
+            JP V0, 0x300
+            EXIT
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x00, 0xE0,
+            0x60, 0x05,
+            0x80, 0x14,
+            0x3A, 0x0A,
+            0xD2, 0x36,
+            0xFF, 0x55,
+            0xF4, 0x65,
+            0x81, 0x16,
+            0xB3, 0x00,
+            0x00, 0xFD,
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_super_chip() {
+        let program = assemble("
+            HIGH
+            SCD 4
+            SCR
+            SCL
+            LD HF, V3
+            LD R, V7
+            LD V7, R
+            LOW
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x00, 0xFF,
+            0x00, 0xC4,
+            0x00, 0xFB,
+            0x00, 0xFC,
+            0xF3, 0x30,
+            0xF7, 0x75,
+            0xF7, 0x85,
+            0x00, 0xFE,
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_xo_chip() {
+        let program = assemble("
+            LD I, LONG data
+            SAVE V1, V3
+            LOAD V3, V1
+            PLANE 3
+            AUDIO
+            PITCH V2
+            SCU 5
+        data:
+            DB 0x2A
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0xF0, 0x00, 0x02, 0x10,
+            0x51, 0x32,
+            0x53, 0x13,
+            0xF3, 0x01,
+            0xF0, 0x02,
+            0xF2, 0x3A,
+            0x00, 0xD5,
+            0x2A,
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_labels_and_constants() {
+        let program = assemble("
+            SPEED = 3
+        start:
+            CALL routine
+            JP start
+        routine: ADD V0, SPEED
+            RET
+        sprite:
+            DB 0xBA, 0x7C, 0b11111110
+            DW sprite + 1, -1
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x22, 0x04,
+            0x12, 0x00,
+            0x70, 0x03,
+            0x00, 0xEE,
+            0xBA, 0x7C, 0xFE,
+            0x02, 0x09, 0xFF, 0xFF,
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_round_trips_disassembly() {
+        for op in (0..=0xFFFFu16).step_by(7) {
+            if let Ok(instruction) = Instruction::decode(op) {
+                let program = assemble(&instruction.to_string()).unwrap();
+                assert_eq!(program, op.to_be_bytes().to_vec(), "{}", instruction);
+            }
+        }
+
+        // F000 is not a multiple of 7, and takes its address from the next word.
+        let instruction = Instruction::decode(0xF000).unwrap();
+        let program = assemble(&format!("{} 0x1234", instruction)).unwrap();
+        assert_eq!(program, vec![0xF0, 0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(error_message("CLS\nFOO V0"), "<source>:2: Unknown instruction `FOO`");
+        assert_eq!(error_message("LD V0, 256"), "<source>:1: 256 does not fit in a byte");
+        assert_eq!(error_message("JP nowhere"), "<source>:1: Undefined symbol `nowhere`");
+        assert_eq!(error_message("a:\na:"), "<source>:2: `a` is already defined at <source>:1");
+        assert_eq!(error_message("ADD V0, DT"), "<source>:1: Invalid operands for ADD: V0, DT");
+        assert_eq!(error_message("DRW V0, V1, 16"), "<source>:1: 16 does not fit in a nibble");
+        assert_eq!(error_message("FOO = BAR\nBAR = FOO\nJP FOO"), "<source>:1: `FOO` is defined in terms of itself");
+        assert_eq!(error_message("DB"), "<source>:1: DB needs at least one value");
+        assert_eq!(error_message("LD V0, 0xZZ"), "<source>:1: Invalid value `0xZZ`");
+        assert_eq!(error_message("PLANE 4"), "<source>:1: 4 does not fit in a plane mask");
+        assert_eq!(error_message("LD V0, LONG 0x1234"), "<source>:1: Invalid operands for LD: V0, a long address");
+        assert_eq!(error_message("DB 0x7FFFFFFFFFFFFFFF + 1"), "<source>:1: Value out of range");
+        assert_eq!(error_message("DB -0x7FFFFFFFFFFFFFFF - 2"), "<source>:1: Value out of range");
+        assert_eq!(error_message("SYS 0x0E0"), "<source>:1: 0x0e0 is not a SYS address; it decodes as CLS");
+        assert_eq!(error_message("SYS 0x0C4"), "<source>:1: 0x0c4 is not a SYS address; it decodes as SCD 4");
+    }
+
+    #[test]
+    fn test_assemble_include() {
+        let dir = env::temp_dir().join(format!("chip8-asm-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.asm"), "CALL draw\nEXIT\nINCLUDE \"lib.asm\"\n").unwrap();
+        fs::write(dir.join("lib.asm"), "draw:\n  CLS\n  RET\n").unwrap();
+        fs::write(dir.join("loop.asm"), "INCLUDE \"loop.asm\"\n").unwrap();
+
+        let program = assemble_file(dir.join("main.asm"));
+        let looped = assemble_file(dir.join("loop.asm"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(program.unwrap(), vec![0x22, 0x04, 0x00, 0xFD, 0x00, 0xE0, 0x00, 0xEE]);
+        assert_eq!(looped.unwrap_err().message, "`loop.asm` includes itself");
+    }
+}
@@ -0,0 +1,66 @@
+use chip8::{ assembler, octo };
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "Usage: chip8-asm <source.asm | source.8o> [-o <rom.ch8>]
+
+Files ending in .8o are compiled as Octo source.
+
+Options:
+    -o <path>    Write the assembled ROM to <path> (default: source with a .ch8 extension)";
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => { output = Some(path); },
+                None => {
+                    eprintln!("-o needs a path\n\n{}", USAGE);
+                    process::exit(2);
+                },
+            },
+            _ if arg.starts_with('-') || source.is_some() => {
+                eprintln!("Unexpected argument: {}\n\n{}", arg, USAGE);
+                process::exit(2);
+            },
+            _ => { source = Some(arg); },
+        }
+    }
+
+    let source = match source {
+        Some(path) => path,
+        None => {
+            eprintln!("No source file given\n\n{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    let result = if source.ends_with(".8o") {
+        octo::compile_file(&source)
+    } else {
+        assembler::assemble_file(&source)
+    };
+
+    let rom = match result {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
+
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&source).with_extension("ch8").to_string_lossy().into_owned()
+    });
+
+    if let Err(err) = fs::write(&output, &rom) {
+        eprintln!("{}: {}", output, err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+use chip8::disassembler;
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "Usage: chip8-disasm <rom.ch8> [--linear]
+
+Options:
+    --linear    Decode every word in order instead of tracing control flow from 0x200";
+
+fn main() {
+    let mut rom = None;
+    let mut linear = false;
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--linear" => { linear = true; },
+            _ if arg.starts_with("--") || rom.is_some() => {
+                eprintln!("Unexpected argument: {}\n\n{}", arg, USAGE);
+                process::exit(2);
+            },
+            _ => { rom = Some(arg); },
+        }
+    }
+
+    let path = match rom {
+        Some(path) => path,
+        None => {
+            eprintln!("No ROM given\n\n{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        },
+    };
+
+    let lines = if linear {
+        disassembler::disassemble_linear(&bytes)
+    } else {
+        disassembler::disassemble(&bytes)
+    };
+
+    for line in lines {
+        println!("{}", line);
+    }
+}
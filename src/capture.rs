@@ -0,0 +1,265 @@
+//! Saves what a program drew: single frames as PNG and whole runs as
+//! animated GIF, one frame per 60 Hz tick.
+
+use crate::cpu::{ Framebuffer, Palette, TIMER_FREQUENCY };
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The scale is zero or a `width` by `height` display would be too large
+    /// for the format at that scale.
+    InvalidSize { width: usize, height: usize, scale: usize },
+    Io(io::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureError::InvalidSize { width, height, scale } =>
+                write!(f, "Cannot write a {}x{} display at scale {}", width, height, scale),
+            CaptureError::Io(err) => write!(f, "Could not write image: {}", err),
+            CaptureError::Png(err) => write!(f, "Could not encode PNG: {}", err),
+            CaptureError::Gif(err) => write!(f, "Could not encode GIF: {}", err),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CaptureError::InvalidSize { .. } => None,
+            CaptureError::Io(err) => Some(err),
+            CaptureError::Png(err) => Some(err),
+            CaptureError::Gif(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(err: io::Error) -> Self {
+        CaptureError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for CaptureError {
+    fn from(err: png::EncodingError) -> Self {
+        CaptureError::Png(err)
+    }
+}
+
+impl From<gif::EncodingError> for CaptureError {
+    fn from(err: gif::EncodingError) -> Self {
+        CaptureError::Gif(err)
+    }
+}
+
+// The size in image pixels of a `width` by `height` display drawn at
+// `scale`, if that is neither empty nor larger than `max` either way.
+fn scaled_size(width: usize, height: usize, scale: usize, max: usize) -> Result<(usize, usize), CaptureError> {
+    match (width.checked_mul(scale), height.checked_mul(scale)) {
+        (Some(scaled_width), Some(scaled_height)) if scale > 0 && scaled_width <= max && scaled_height <= max =>
+            Ok((scaled_width, scaled_height)),
+        _ => Err(CaptureError::InvalidSize { width, height, scale }),
+    }
+}
+
+// Samples the framebuffer onto a `width` by `height` canvas, so a low
+// resolution frame fills the same area as a high resolution one.
+fn sample<T: Copy>(framebuffer: &Framebuffer, width: usize, height: usize, color: impl Fn(u8) -> T) -> Vec<T> {
+    let mut pixels = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let source_y = y * framebuffer.height() / height;
+        for x in 0..width {
+            let source_x = x * framebuffer.width() / width;
+            pixels.push(color(framebuffer.pixel(source_x, source_y)));
+        }
+    }
+
+    pixels
+}
+
+/// Writes the framebuffer as an RGBA PNG, each pixel `scale` pixels square.
+pub fn write_png<W: Write>(out: W, framebuffer: &Framebuffer, scale: usize, palette: &Palette) -> Result<(), CaptureError> {
+    let (width, height) = scaled_size(framebuffer.width(), framebuffer.height(), scale, u32::MAX as usize)?;
+
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let pixels = sample(framebuffer, width, height, |mask| palette.colors[mask as usize]);
+    encoder.write_header()?.write_image_data(&pixels.concat())?;
+    Ok(())
+}
+
+pub fn save_png<P: AsRef<Path>>(path: P, framebuffer: &Framebuffer, scale: usize, palette: &Palette) -> Result<(), CaptureError> {
+    write_png(BufWriter::new(File::create(path)?), framebuffer, scale, palette)
+}
+
+// Viewers stretch GIF delays below two hundredths of a second, and a 60 Hz
+// tick lasts 1.67 of them. Three ticks are exactly five hundredths, so one
+// frame is kept per three ticks: 20 frames a second at the right speed.
+const TICKS_PER_FRAME: u64 = 3;
+
+/// Records frames into a looping animated GIF. Frames are stretched to the
+/// size given on creation, so resolution changes keep the same image size.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u16,
+    height: u16,
+    ticks: u64,
+    frames: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// Starts a GIF `width` by `height` display pixels large, each drawn
+    /// `scale` pixels square.
+    pub fn new(out: W, width: usize, height: usize, scale: usize, palette: &Palette) -> Result<Self, CaptureError> {
+        let (scaled_width, scaled_height) = scaled_size(width, height, scale, u16::MAX as usize)?;
+
+        let colors: Vec<u8> = palette.colors.iter().flat_map(|color| color[..3].to_vec()).collect();
+        let mut encoder = gif::Encoder::new(out, scaled_width as u16, scaled_height as u16, &colors)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(GifRecorder { encoder, width: scaled_width as u16, height: scaled_height as u16, ticks: 0, frames: 0 })
+    }
+
+    /// Records the framebuffer at the end of one 60 Hz tick. Only every
+    /// third tick becomes a frame, shown until the next one.
+    pub fn record(&mut self, framebuffer: &Framebuffer) -> Result<(), CaptureError> {
+        let tick = self.ticks;
+        self.ticks += 1;
+        if !tick.is_multiple_of(TICKS_PER_FRAME) {
+            return Ok(());
+        }
+
+        let pixels = sample(framebuffer, self.width as usize, self.height as usize, |mask| mask);
+        let mut frame = gif::Frame::from_indexed_pixels(self.width, self.height, &pixels, None);
+        frame.delay = (TICKS_PER_FRAME * 100 / u64::from(TIMER_FREQUENCY)) as u16;
+
+        self.encoder.write_frame(&frame)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// How many frames have been written to the GIF.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Writes the end of the GIF and returns the underlying writer.
+    pub fn finish(self) -> Result<W, CaptureError> {
+        Ok(self.encoder.into_inner()?)
+    }
+}
+
+impl GifRecorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, width: usize, height: usize, scale: usize, palette: &Palette) -> Result<Self, CaptureError> {
+        GifRecorder::new(BufWriter::new(File::create(path)?), width, height, scale, palette)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn test_write_png() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, sprite
+            DRW V0, V0, 1
+            EXIT
+        sprite:
+            DB 0b10000000
+        ");
+
+        chip8.run().unwrap();
+
+        let mut out = Vec::new();
+        write_png(&mut out, &chip8.framebuffer(), 2, &Palette::MONOCHROME).unwrap();
+
+        let decoder = png::Decoder::new(&out[..]);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        let row = 128 * 4;
+        assert_eq!(&pixels[0..4], &Palette::MONOCHROME.colors[1]);
+        assert_eq!(&pixels[4..8], &Palette::MONOCHROME.colors[1]);
+        assert_eq!(&pixels[row + 4..row + 8], &Palette::MONOCHROME.colors[1]);
+        assert_eq!(&pixels[8..12], &Palette::MONOCHROME.colors[0]);
+    }
+
+    #[test]
+    fn test_invalid_scale() {
+        let chip8 = CPU::default();
+
+        match write_png(Vec::new(), &chip8.framebuffer(), 0, &Palette::MONOCHROME) {
+            Err(CaptureError::InvalidSize { width: 64, height: 32, scale: 0 }) => {},
+            other => panic!("Expected InvalidSize, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scale_too_large() {
+        let chip8 = CPU::default();
+
+        match write_png(Vec::new(), &chip8.framebuffer(), usize::MAX, &Palette::MONOCHROME) {
+            Err(CaptureError::InvalidSize { scale: usize::MAX, .. }) => {},
+            other => panic!("Expected InvalidSize, got {:?}", other),
+        }
+
+        match GifRecorder::new(Vec::new(), 128, 64, usize::MAX / 64, &Palette::MONOCHROME) {
+            Err(CaptureError::InvalidSize { width: 128, height: 64, .. }) => {},
+            Err(err) => panic!("Expected InvalidSize, got {:?}", err),
+            Ok(_) => panic!("Expected InvalidSize"),
+        }
+    }
+
+    #[test]
+    fn test_record_gif() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, sprite
+            DRW V0, V0, 1
+            EXIT
+        sprite:
+            DB 0b10000000
+        ");
+
+        chip8.run().unwrap();
+
+        let mut recorder = GifRecorder::new(Vec::new(), 128, 64, 1, &Palette::OCTO).unwrap();
+        for _ in 0..7 {
+            recorder.record(&chip8.framebuffer()).unwrap();
+        }
+        assert_eq!(recorder.frames(), 3);
+        let out = recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(gif::ColorOutput::Indexed);
+        let mut reader = decoder.read_info(&out[..]).unwrap();
+        assert_eq!((reader.width(), reader.height()), (128, 64));
+
+        let mut delays = Vec::new();
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            // The low resolution frame is stretched to fill the canvas.
+            assert_eq!(&frame.buffer[..3], &[1, 1, 0]);
+            assert_eq!(frame.buffer[128], 1);
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![5, 5, 5]);
+    }
+}
@@ -4,27 +4,91 @@ mod register_operations;
 mod register_i;
 mod misc;
 mod display;
+mod load_operations;
+mod timers;
+mod keypad;
+mod font;
+mod rom;
+mod error;
+mod stepping;
+mod instruction;
+mod quirks;
+mod audio;
+mod variant;
+mod framebuffer;
+mod savestate;
+mod random;
 
-extern crate rand;
-
-use rand::Rng;
+pub use self::keypad::Keypad;
+pub use self::font::{ Font, FontError, STANDARD_GLYPHS, VIP_GLYPHS, LARGE_GLYPHS };
+pub use self::misc::SysBehaviour;
+pub use self::rom::LoadError;
+pub use self::error::Chip8Error;
+pub use self::stepping::StepResult;
+pub use self::instruction::{ Instruction, DecodeError };
+pub use self::quirks::{ Quirks, QUIRK_PRESETS };
+pub use self::random::{ RandomSource, SeededRandom, EntropyRandom, ScriptedRandom };
+pub use self::savestate::{ StateError, STATE_VERSION };
+pub use self::framebuffer::{ Framebuffer, Palette, PALETTE_PRESETS };
+pub use self::variant::{ Variant, InstructionSet, Chip8, Chip8X, Chip48, SuperChip, XoChip, VARIANTS, variant, suggest_variant };
 
 pub struct CPU {
     registers: [u8; 16],
-    memory: [u8; 4096],
+    memory: Vec<u8>,
     program_counter: usize,
     stack: [u16; 16],
     stack_pointer: usize,
     i: u16,
-    seed: [u64; 4],
-    display: [[bool; 32]; 64],
+    random: Box<dyn RandomSource>,
+    display: [Plane; PLANE_COUNT],
+    selected_planes: u8,
+    hires: bool,
+    dirty_rows: [bool; HIRES_DISPLAY_HEIGHT],
+    font_addr: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    buzzed: bool,
+    instructions_per_second: u32,
+    timer_cycles: u32,
+    keypad: Keypad,
+    waiting_for_key: Option<usize>,
+    sys_behaviour: SysBehaviour,
+    sys_call: Option<u16>,
+    halted: bool,
+    quirks: Quirks,
+    variant: Option<&'static dyn Variant>,
+    flags: [u8; 16],
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    has_audio_pattern: bool,
+    pitch: u8,
 }
 
-const PROGRAM_START_ADDR: usize = 0x200 as usize;
+// One bit plane of the display, indexed by column then row.
+type Plane = [[bool; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH];
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+pub const PROGRAM_START_ADDR: usize = 0x200;
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START_ADDR;
+pub const MAX_XO_PROGRAM_SIZE: usize = XO_MEMORY_SIZE - PROGRAM_START_ADDR;
+pub const TIMER_FREQUENCY: u32 = 60;
+const FONT_START_ADDR: usize = 0x050;
+const FONT_SPRITE_SIZE: usize = 5;
+const FONT_SIZE: usize = FONT_SPRITE_SIZE * 16;
+const LARGE_FONT_START_ADDR: usize = FONT_START_ADDR + FONT_SIZE;
+const LARGE_FONT_SPRITE_SIZE: usize = 10;
+const LARGE_FONT_SIZE: usize = LARGE_FONT_SPRITE_SIZE * 16;
+const DISPLAY_WIDTH: usize = 64;
+const DISPLAY_HEIGHT: usize = 32;
+const HIRES_DISPLAY_WIDTH: usize = 128;
+const HIRES_DISPLAY_HEIGHT: usize = 64;
+const PLANE_COUNT: usize = 2;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 500;
 
 const MISC: u8 = 0x0 as u8;
 const SUBROUTINE: u8 = 0x2 as u8;
-const ENDROUTINE: u8 = 0xEE as u8;
 const JUMP: u8 = 0x1 as u8;
 const SKIP_IF_EQUAL: u8 = 0x3 as u8;
 const SKIP_IF_NOT_EQUAL: u8 = 0x4 as u8;
@@ -37,6 +101,19 @@ const STORE_ADDR_I: u8 = 0xA as u8;
 const JUMP_ADDR_PLUS_V0: u8 = 0xB as u8;
 const RANDOM_AND: u8 = 0xC as u8;
 const DISPLAY: u8 = 0xD as u8;
+const KEY_OPERATION: u8 = 0xE;
+const LOAD_OPERATION: u8 = 0xF;
+
+// Misc Actions
+const CLEAR_SCREEN: u16 = 0x0E0;
+const ENDROUTINE: u16 = 0x0EE;
+const EXIT: u16 = 0x0FD;
+const SCROLL_DOWN: u16 = 0x0C0;
+const SCROLL_UP: u16 = 0x0D0;
+const SCROLL_RIGHT: u16 = 0x0FB;
+const SCROLL_LEFT: u16 = 0x0FC;
+const LOW_RES: u16 = 0x0FE;
+const HIGH_RES: u16 = 0x0FF;
 
 // Register Actions
 const REGISTER_STORE: u8 = 0x0 as u8;
@@ -49,110 +126,224 @@ const REGISTER_SHIFT_RIGHT: u8 = 0x6 as u8;
 const REGISTER_SUBN: u8 = 0x7 as u8;
 const REGISTER_SHIFT_LEFT: u8 = 0xE as u8;
 
+// Register Range Actions
+const STORE_REGISTER_RANGE: u8 = 0x2;
+const LOAD_REGISTER_RANGE: u8 = 0x3;
+
+// Key Actions
+const SKIP_IF_KEY_PRESSED: u8 = 0x9E;
+const SKIP_IF_KEY_NOT_PRESSED: u8 = 0xA1;
+
+// Load Actions
+const STORE_I_LONG: u8 = 0x00;
+const SELECT_PLANES: u8 = 0x01;
+const LOAD_AUDIO_PATTERN: u8 = 0x02;
+const LOAD_DELAY_TIMER: u8 = 0x07;
+const LOAD_KEY_PRESS: u8 = 0x0A;
+const SET_DELAY_TIMER: u8 = 0x15;
+const SET_SOUND_TIMER: u8 = 0x18;
+const ADD_TO_I: u8 = 0x1E;
+const LOAD_SPRITE_ADDR: u8 = 0x29;
+const LOAD_LARGE_SPRITE_ADDR: u8 = 0x30;
+const STORE_BCD: u8 = 0x33;
+const SET_PITCH: u8 = 0x3A;
+const STORE_REGISTERS: u8 = 0x55;
+const LOAD_REGISTERS: u8 = 0x65;
+const STORE_FLAGS: u8 = 0x75;
+const LOAD_FLAGS: u8 = 0x85;
+
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new(Quirks::default())
+    }
+}
 
 impl CPU {
-    pub fn new() -> Self {
-        let seeds: [u64; 4] = [
-            rand::thread_rng().gen::<u64>(),
-            rand::thread_rng().gen::<u64>(),
-            rand::thread_rng().gen::<u64>(),
-            rand::thread_rng().gen::<u64>(),
-        ];
-
-        CPU { 
+    pub fn new(quirks: Quirks) -> Self {
+        let mut cpu = CPU { 
             registers: [0; 16], 
-            memory: [0; 4096], 
+            memory: vec![0; MEMORY_SIZE], 
             program_counter: 0, 
             stack: [0; 16], 
             stack_pointer: 0, 
             i: 0,
-            seed: seeds,
-            display: [[false; 32]; 64],
-        }
+            random: Box::new(EntropyRandom),
+            display: [[[false; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH]; PLANE_COUNT],
+            selected_planes: 1,
+            hires: false,
+            dirty_rows: [false; HIRES_DISPLAY_HEIGHT],
+            font_addr: FONT_START_ADDR,
+            delay_timer: 0,
+            sound_timer: 0,
+            buzzed: false,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            timer_cycles: 0,
+            keypad: Keypad::new(),
+            waiting_for_key: None,
+            sys_behaviour: SysBehaviour::Ignore,
+            sys_call: None,
+            halted: false,
+            quirks,
+            variant: None,
+            flags: [0; 16],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            has_audio_pattern: false,
+            pitch: DEFAULT_PITCH,
+        };
+
+        cpu.set_font(&Font::default());
+        cpu.memory[LARGE_FONT_START_ADDR..LARGE_FONT_START_ADDR + LARGE_FONT_SIZE].copy_from_slice(&LARGE_GLYPHS);
+        cpu
+    }
+
+    /// Creates a CPU with the memory, quirks and instruction set of `variant`.
+    /// Opcodes the variant does not support stop execution with an error.
+    pub fn with_variant(variant: &'static dyn Variant) -> Self {
+        let mut cpu = CPU::new(variant.quirks());
+        cpu.set_memory_size(variant.memory_size());
+        cpu.variant = Some(variant);
+        cpu
+    }
+
+    /// The variant this CPU was created for, or `None` if every instruction
+    /// is accepted.
+    pub fn variant(&self) -> Option<&'static dyn Variant> {
+        self.variant
     }
 
-    fn blank_program(&mut self) -> [u8; 3176] {
-        [0; 3176]
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn load_asm(&mut self, source: &str) {
+        let program = crate::assembler::assemble(source).unwrap();
+        self.load(&program).unwrap();
     }
 
     fn advance_counter(&mut self) {
         self.program_counter += 2;
     }
 
-    pub fn load(&mut self, program: [u8; 3176]) {
-        let mut program_counter = PROGRAM_START_ADDR;
-        for e in program.iter() {
-            self.memory[program_counter] = *e;
-            program_counter += 1
+    // Skips the next instruction, which is four bytes long if it is F000 nnnn.
+    fn skip_instruction(&mut self) {
+        let pc = self.program_counter;
+        let long = pc + 1 < self.memory.len() && self.memory[pc] == 0xF0 && self.memory[pc + 1] == 0x00;
+
+        self.program_counter += if long { 4 } else { 2 };
+    }
+
+    fn check_memory(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        if addr + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { addr });
         }
 
-        self.program_counter = PROGRAM_START_ADDR;
+        Ok(())
     }
-    
-    fn set_seed(&mut self, seed: [u64; 4]) {
-        self.seed = seed;
+
+    /// Makes Cxkk deterministic: the same seed always gives the same
+    /// sequence, see `SeededRandom`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = Box::new(SeededRandom::new(seed));
+    }
+
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.random = source;
     }
 
-    pub fn run(&mut self) {
-        loop {
-            if self.program_counter >= self.memory.len() {
-                println!("End of memory, exiting..\n");
-                return;
-            }
-
-            let op_byte_1 = self.memory[self.program_counter] as u16;
-            let op_byte_2 = self.memory[self.program_counter + 1] as u16;
-            let op = op_byte_1 << 8 | op_byte_2;
-
-            let op_code = ((op & 0xF000) >> 12) as u8;
-            let x = ((op & 0x0F00) >> 8) as usize;
-            let y = ((op & 0x00F0) >> 4) as usize;
-            let value = (op & 0x000F) as u8;
-            let addr = (op & 0x0FFF) as u16;
-            let byte = (op & 0x00FF) as u8;
-
-            self.advance_counter();
-
-            match op_code {
-                MISC => {
-                    match byte {
-                        ENDROUTINE => { self.ret(); },
-                        _ => {
-                            println!("0x0 op code at {:04x}, exiting now..\n", self.program_counter);
-                            return;
-                        },
-                    }
-                },
-                JUMP => { self.jump(addr); },
-                SUBROUTINE => { self.call(addr); },
-                SKIP_IF_EQUAL => { self.skip_if_equal(x, byte); },
-                SKIP_IF_NOT_EQUAL => { self.skip_if_not_equal(x, byte); },
-                SKIP_IF_REGISTER_EQUAL => { self.skip_if_registers_equal(x, y); },
-                SKIP_IF_REGISTER_NOT_EQUAL => { self.skip_if_registers_not_equal(x, y); },
-                STORE_VALUE_TO_REGISTER => { self.store_register(x, byte); },
-                ADD_VALUE_TO_REGISTER => { self.add_register(x, byte); },
-                REGISTER_OPERATION => {
-
-                    match value {
-                        REGISTER_STORE => { self.copy(x, y); },
-                        REGISTER_OR => { self.or(x, y); },
-                        REGISTER_AND => { self.and(x, y); },
-                        REGISTER_XOR => { self.xor(x, y); },
-                        REGISTER_ADD => { self.add(x, y); },
-                        REGISTER_SUB => { self.sub(x, y); },
-                        REGISTER_SHIFT_RIGHT => { self.shift_right(x, y); },
-                        REGISTER_SUBN => { self.subn(x, y); },
-                        REGISTER_SHIFT_LEFT => { self.shift_left(x, y); },
-                        _ => unimplemented!("No imple for {:04x} - {:04x}", op_code, value),
-                    }
-
-                }
-                STORE_ADDR_I => { self.store_register_i(addr); },
-                JUMP_ADDR_PLUS_V0 => { self.jump_add_v0(addr); },
-                RANDOM_AND => { self.random(x, byte); },
-                DISPLAY => { self.draw(x, y, value); },
-                _ => unimplemented!("No imple for {:04x}", op_code),
-            }
+    /// Fetches, decodes and executes the instruction at the program counter.
+    pub fn step(&mut self) -> Result<StepResult, Chip8Error> {
+        if self.halted {
+            return Ok(StepResult::Halted);
         }
+
+        if self.waiting_for_key.is_some() {
+            return Ok(StepResult::WaitingForKey);
+        }
+
+        if self.program_counter + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { addr: self.program_counter });
+        }
+
+        let op_byte_1 = self.memory[self.program_counter] as u16;
+        let op_byte_2 = self.memory[self.program_counter + 1] as u16;
+        let op = op_byte_1 << 8 | op_byte_2;
+
+        let op_addr = self.program_counter;
+        let instruction = Instruction::decode(op)
+            .map_err(|_| Chip8Error::UnknownOpcode { opcode: op, addr: op_addr })?;
+
+        if let Some(variant) = self.variant.filter(|variant| !variant.supports(&instruction)) {
+            return Err(Chip8Error::UnsupportedOpcode { opcode: op, addr: op_addr, variant: variant.name() });
+        }
+
+        self.advance_counter();
+        self.execute(instruction)?;
+        self.cycle_timers();
+
+        Ok(StepResult::Executed { addr: op_addr, opcode: op })
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        match instruction {
+            Instruction::Clear => { self.clear(); },
+            Instruction::Return => { self.ret()?; },
+            Instruction::Exit => { self.halt(); },
+            Instruction::ScrollDown { n } => { self.scroll_down(n as usize); },
+            Instruction::ScrollUp { n } => { self.scroll_up(n as usize); },
+            Instruction::ScrollRight => { self.scroll_right(); },
+            Instruction::ScrollLeft => { self.scroll_left(); },
+            Instruction::LowRes => { self.set_hires(false); },
+            Instruction::HighRes => { self.set_hires(true); },
+            Instruction::Sys { addr } => { self.sys(addr); },
+            Instruction::Jump { addr } => { self.jump(addr); },
+            Instruction::Call { addr } => { self.call(addr)?; },
+            Instruction::SkipIfEqual { x, byte } => { self.skip_if_equal(x as usize, byte); },
+            Instruction::SkipIfNotEqual { x, byte } => { self.skip_if_not_equal(x as usize, byte); },
+            Instruction::SkipIfRegistersEqual { x, y } => { self.skip_if_registers_equal(x as usize, y as usize); },
+            Instruction::SkipIfRegistersNotEqual { x, y } => { self.skip_if_registers_not_equal(x as usize, y as usize); },
+            Instruction::StoreRange { x, y } => { self.store_register_range(x as usize, y as usize)?; },
+            Instruction::LoadRange { x, y } => { self.load_register_range(x as usize, y as usize)?; },
+            Instruction::Store { x, byte } => { self.store_register(x as usize, byte); },
+            Instruction::AddValue { x, byte } => { self.add_register(x as usize, byte); },
+            Instruction::Copy { x, y } => { self.copy(x as usize, y as usize); },
+            Instruction::Or { x, y } => { self.or(x as usize, y as usize); },
+            Instruction::And { x, y } => { self.and(x as usize, y as usize); },
+            Instruction::Xor { x, y } => { self.xor(x as usize, y as usize); },
+            Instruction::Add { x, y } => { self.add(x as usize, y as usize); },
+            Instruction::Sub { x, y } => { self.sub(x as usize, y as usize); },
+            Instruction::ShiftRight { x, y } => { self.shift_right(x as usize, y as usize); },
+            Instruction::Subn { x, y } => { self.subn(x as usize, y as usize); },
+            Instruction::ShiftLeft { x, y } => { self.shift_left(x as usize, y as usize); },
+            Instruction::StoreI { addr } => { self.store_register_i(addr); },
+            Instruction::StoreILong => { self.store_register_i_long()?; },
+            Instruction::JumpV0 { addr } => { self.jump_add_v0(addr); },
+            Instruction::Random { x, byte } => { self.random(x as usize, byte); },
+            Instruction::Draw { x, y, n } => { self.draw(x as usize, y as usize, n)?; },
+            Instruction::SkipIfKeyPressed { x } => { self.skip_if_key_pressed(x as usize); },
+            Instruction::SkipIfKeyNotPressed { x } => { self.skip_if_key_not_pressed(x as usize); },
+            Instruction::SelectPlanes { n } => { self.select_planes(n); },
+            Instruction::LoadAudioPattern => { self.load_audio_pattern()?; },
+            Instruction::SetPitch { x } => { self.set_pitch(x as usize); },
+            Instruction::LoadDelayTimer { x } => { self.load_delay_timer(x as usize); },
+            Instruction::LoadKeyPress { x } => { self.load_key_press(x as usize); },
+            Instruction::SetDelayTimer { x } => { self.set_delay_timer(x as usize); },
+            Instruction::SetSoundTimer { x } => { self.set_sound_timer(x as usize); },
+            Instruction::AddToI { x } => { self.add_to_i(x as usize); },
+            Instruction::LoadSpriteAddr { x } => { self.load_sprite_addr(x as usize); },
+            Instruction::LoadLargeSpriteAddr { x } => { self.load_large_sprite_addr(x as usize); },
+            Instruction::StoreBcd { x } => { self.store_bcd(x as usize)?; },
+            Instruction::StoreRegisters { x } => { self.store_registers(x as usize)?; },
+            Instruction::LoadRegisters { x } => { self.load_registers(x as usize)?; },
+            Instruction::StoreFlags { x } => { self.store_flags(x as usize); },
+            Instruction::LoadFlags { x } => { self.load_flags(x as usize); },
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,75 @@
+use super::{ CPU, Chip8Error, AUDIO_PATTERN_SIZE };
+
+impl CPU {
+    /// The 128-bit XO-CHIP sample pattern played while the sound timer runs.
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
+    }
+
+    /// Whether the program has loaded an audio pattern with F002. Until then
+    /// the buzzer is a plain square wave.
+    pub fn has_audio_pattern(&self) -> bool {
+        self.has_audio_pattern
+    }
+
+    /// Playback rate of the audio pattern is 4000 * 2^((pitch - 64) / 48) Hz.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub(super) fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        self.check_memory(addr, AUDIO_PATTERN_SIZE)?;
+
+        self.audio_pattern.copy_from_slice(&self.memory[addr..addr + AUDIO_PATTERN_SIZE]);
+        self.has_audio_pattern = true;
+        Ok(())
+    }
+
+    pub(super) fn set_pitch(&mut self, x: usize) {
+        self.pitch = self.registers[x];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_audio_pattern() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, pattern
+            AUDIO
+            EXIT
+        pattern:
+            DB 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00
+            DB 0x0F, 0xF0, 0x0F, 0xF0, 0x0F, 0xF0, 0x0F, 0xF0
+        ");
+        assert_eq!(chip8.audio_pattern(), &[0; AUDIO_PATTERN_SIZE]);
+        assert!(!chip8.has_audio_pattern());
+
+        chip8.run().unwrap();
+        assert!(chip8.has_audio_pattern());
+        assert_eq!(chip8.audio_pattern()[0], 0xFF);
+        assert_eq!(chip8.audio_pattern()[1], 0x00);
+        assert_eq!(chip8.audio_pattern()[15], 0xF0);
+    }
+
+    #[test]
+    fn test_set_pitch() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[7] = 112;
+
+        chip8.load_asm("
+            PITCH V7
+            EXIT
+        ");
+        assert_eq!(chip8.pitch(), 64);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.pitch(), 112);
+    }
+}
@@ -3,25 +3,25 @@ use super::CPU;
 impl CPU {
     pub(super) fn skip_if_equal(&mut self, x: usize, value: u8) {
         if self.registers[x] == value {
-            self.advance_counter();
+            self.skip_instruction();
         }
     }
 
     pub(super) fn skip_if_not_equal(&mut self, x: usize, value: u8) {
         if self.registers[x] != value {
-            self.advance_counter();
+            self.skip_instruction();
         }
     }
 
     pub(super) fn skip_if_registers_equal(&mut self, x: usize, y: usize) {
         if self.registers[x] == self.registers[y] {
-            self.advance_counter();
+            self.skip_instruction();
         }
     }
 
     pub(super) fn skip_if_registers_not_equal(&mut self, x: usize, y: usize) {
         if self.registers[x] != self.registers[y] {
-            self.advance_counter();
+            self.skip_instruction();
         }
     }
 }
@@ -33,96 +33,140 @@ mod tests {
 
     #[test]
     fn test_skip_if_equal() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 6;
 
-        test[0] = 0x30 as u8; test[1] = 0x05 as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[4] = 0x80 as u8; test[5] = 0x14 as u8;
-        test[6] = 0x30 as u8; test[7] = 0x0C as u8;
-        test[8] = 0x80 as u8; test[9] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SE V0, 0x05
+            ADD V0, V1
+            ADD V0, V1
+            SE V0, 0x0c
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 6);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 17);
         assert_eq!(chip8.registers[1], 6);
     }
 
+    #[test]
+    fn test_skip_over_long_instruction() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            SE V0, 0x00
+            LD I, LONG 0x0123
+            LD V1, 0x01
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.registers[1], 1);
+    }
+
     #[test]
     fn test_skip_if_not_equal() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 6;
 
-        test[0] = 0x40 as u8; test[1] = 0x06 as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[4] = 0x80 as u8; test[5] = 0x14 as u8;
-        test[6] = 0x40 as u8; test[7] = 0x0B as u8;
-        test[8] = 0x80 as u8; test[9] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SNE V0, 0x06
+            ADD V0, V1
+            ADD V0, V1
+            SNE V0, 0x0b
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 6);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 17);
         assert_eq!(chip8.registers[1], 6);
     }
 
     #[test]
     fn test_skip_if_registers_equal() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 5;
 
-        test[0] = 0x50 as u8; test[1] = 0x10 as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[4] = 0x80 as u8; test[5] = 0x15 as u8;
-        test[6] = 0x50 as u8; test[7] = 0x10 as u8;
-        test[8] = 0x80 as u8; test[9] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SE V0, V1
+            ADD V0, V1
+            SUB V0, V1
+            SE V0, V1
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 5);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 5);
     }
 
     #[test]
     fn test_skip_if_registers_not_equal() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 9;
         chip8.registers[1] = 2;
         chip8.registers[2] = 2;
 
-        test[0] = 0x90 as u8; test[1] = 0x10 as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[4] = 0x80 as u8; test[5] = 0x15 as u8;
-        test[6] = 0x91 as u8; test[7] = 0x20 as u8;
-        test[8] = 0x80 as u8; test[9] = 0x15 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SNE V0, V1
+            ADD V0, V1
+            SUB V0, V1
+            SNE V1, V2
+            SUB V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 9);
         assert_eq!(chip8.registers[1], 2);
         assert_eq!(chip8.registers[2], 2);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 2);
         assert_eq!(chip8.registers[2], 2);
     }
+
+    #[test]
+    fn test_octo_comparisons() {
+        let mut chip8 = CPU::default();
+
+        let program = crate::octo::compile("
+            : main
+                v0 := 3
+                v1 := 5
+                if v0 < v1 then v2 := 1
+                if v0 > v1 then v3 := 1
+                if v0 <= 3 then v4 := 1
+                if v1 >= 6 then v5 := 1
+                loop
+                    v6 += 1
+                    while v6 < 10
+                again
+                exit
+        ").unwrap();
+        chip8.load(&program).unwrap();
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[2], 1);
+        assert_eq!(chip8.registers[3], 0);
+        assert_eq!(chip8.registers[4], 1);
+        assert_eq!(chip8.registers[5], 0);
+        assert_eq!(chip8.registers[6], 10);
+    }
 }
@@ -1,106 +1,580 @@
-use super::CPU;
+use super::{ CPU, Chip8Error, Plane, DISPLAY_WIDTH, DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, PLANE_COUNT };
 use bitvec::prelude::*;
 
+const SCROLL_DISTANCE: usize = 4;
+
 impl CPU {
-    pub(super) fn draw(&mut self, x: usize, y: usize, byte: u8) {
-        let mut lower_bound = self.i as usize;
-        let upper_bound = lower_bound + (byte as usize);
-
-        let mut cur_y = self.registers[y] as usize;
-        while lower_bound < upper_bound {
-            let value: u8 = self.memory[lower_bound];
-            let bv = BitVec::<BigEndian, u8>::from_element(value);
-
-            let mut cur_x = self.registers[x] as usize;
-            
-            for bit in bv {
-                let previous = self.display[cur_x][cur_y];
-                let new = previous != bit;
-                println!("({}, {}) - {} to {}", cur_x, cur_y, previous, new);
-
-                self.display[cur_x][cur_y] = new; 
-
-                if new == false { 
-                    self.registers[0xF] = 0x1 as u8;
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Width of the display in pixels at the current resolution.
+    pub fn display_width(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    /// Height of the display in pixels at the current resolution.
+    pub fn display_height(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    /// Width and height of the largest display the CPU can switch to: its
+    /// variant's, or 128x64 if it has none.
+    pub fn display_size(&self) -> (usize, usize) {
+        self.variant.map_or((HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT), |variant| variant.display_size())
+    }
+
+    /// Renders the display as text, one line per row, `#` for pixels lit
+    /// in any plane.
+    pub fn render_text(&self) -> String {
+        let width = self.display_width();
+        let height = self.display_height();
+
+        let mut text = String::with_capacity((width + 1) * height);
+        for y in 0..height {
+            for x in 0..width {
+                let lit = self.display.iter().any(|plane| plane[x][y]);
+                text.push(if lit { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Bitmask of the planes that drawing, clearing and scrolling act on.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub(super) fn select_planes(&mut self, n: u8) {
+        self.selected_planes = n & ((1 << PLANE_COUNT) - 1);
+    }
+
+    fn planes_mut(&mut self) -> impl Iterator<Item = &mut Plane> {
+        let selected = self.selected_planes;
+        self.display.iter_mut().enumerate()
+            .filter(move |(n, _)| selected & (1 << n) != 0)
+            .map(|(_, plane)| plane)
+    }
+
+    pub(super) fn clear(&mut self) {
+        for plane in self.planes_mut() {
+            *plane = [[false; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH];
+        }
+        self.mark_all_dirty();
+    }
+
+    // Switching resolution clears every plane, not just the selected ones.
+    // A variant without a high resolution display stays in low resolution.
+    pub(super) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires && self.display_size() == (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT);
+        self.display = [[[false; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH]; PLANE_COUNT];
+        self.mark_all_dirty();
+    }
+
+    pub(super) fn scroll_down(&mut self, n: usize) {
+        let height = self.display_height();
+
+        for plane in self.planes_mut() {
+            for column in plane.iter_mut() {
+                for y in (0..height).rev() {
+                    column[y] = y >= n && column[y - n];
+                }
+            }
+        }
+        self.mark_all_dirty();
+    }
+
+    pub(super) fn scroll_up(&mut self, n: usize) {
+        let height = self.display_height();
+
+        for plane in self.planes_mut() {
+            for column in plane.iter_mut() {
+                for y in 0..height {
+                    column[y] = y + n < height && column[y + n];
                 }
+            }
+        }
+        self.mark_all_dirty();
+    }
+
+    pub(super) fn scroll_right(&mut self) {
+        let width = self.display_width();
 
-                cur_x += 1;
+        for plane in self.planes_mut() {
+            for x in (0..width).rev() {
+                plane[x] = if x >= SCROLL_DISTANCE { plane[x - SCROLL_DISTANCE] } else { [false; HIRES_DISPLAY_HEIGHT] };
             }
+        }
+        self.mark_all_dirty();
+    }
+
+    pub(super) fn scroll_left(&mut self) {
+        let width = self.display_width();
 
-            cur_y += 1;
-            lower_bound += 1;
+        for plane in self.planes_mut() {
+            for x in 0..width {
+                plane[x] = if x + SCROLL_DISTANCE < width { plane[x + SCROLL_DISTANCE] } else { [false; HIRES_DISPLAY_HEIGHT] };
+            }
         }
+        self.mark_all_dirty();
+    }
+
+    // A height of 0 draws a 16x16 sprite stored as two bytes per row. With
+    // more than one plane selected, each plane takes the next sprite's worth
+    // of data after I.
+    pub(super) fn draw(&mut self, x: usize, y: usize, byte: u8) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        let (rows, row_bytes) = if byte == 0 { (16, 2) } else { (byte as usize, 1) };
+        let sprite_size = rows * row_bytes;
+        let planes = (0..PLANE_COUNT).filter(|n| self.selected_planes & (1 << n) != 0).collect::<Vec<_>>();
+        self.check_memory(addr, sprite_size * planes.len())?;
+
+        let width = self.display_width();
+        let height = self.display_height();
+
+        // The starting position always wraps; pixels running off the edge
+        // are then clipped or wrapped depending on the quirk.
+        let start_x = self.registers[x] as usize % width;
+        let start_y = self.registers[y] as usize % height;
+
+        self.registers[0xF] = 0;
+
+        for (n, &plane) in planes.iter().enumerate() {
+            let sprite = addr + n * sprite_size;
+
+            for row in 0..rows {
+                for part in 0..row_bytes {
+                    let bits = BitVec::<BigEndian, u8>::from_element(self.memory[sprite + row * row_bytes + part]);
+
+                    for (column, bit) in bits.into_iter().enumerate() {
+                        let (cur_x, cur_y) = (start_x + part * 8 + column, start_y + row);
+                        if !bit || (self.quirks.clip_sprites && (cur_x >= width || cur_y >= height)) {
+                            continue;
+                        }
+
+                        let pixel = &mut self.display[plane][cur_x % width][cur_y % height];
+                        if *pixel {
+                            self.registers[0xF] = 1;
+                        }
+                        *pixel = !*pixel;
+                        self.mark_dirty(cur_y % height);
+                    }
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::{ Quirks, Chip8 };
+
+    #[test]
+    fn test_clear_screen() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][0] = true;
+        chip8.display[0][63][31] = true;
+
+        chip8.load_asm("
+            CLS
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.display[0][0][0]);
+        assert!(!chip8.display[0][63][31]);
+    }
+
+    #[test]
+    fn test_render_text() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][0] = true;
+        chip8.display[0][2][1] = true;
+
+        let text = chip8.render_text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(lines[0].len(), 64);
+        assert!(lines[0].starts_with("#.."));
+        assert!(lines[1].starts_with("..#"));
+        assert!(lines[2].chars().all(|c| c == '.'));
+    }
 
     #[test]
     fn test_display_sprite() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
-
-        test[00] = 0x62 as u8; test[01] = 0x0A as u8;
-        test[02] = 0x63 as u8; test[03] = 0x0C as u8;
-        test[04] = 0xA2 as u8; test[05] = 0x20 as u8;
-        test[06] = 0xD2 as u8; test[07] = 0x36 as u8;
-
-        test[32] = 0xBA as u8; test[33] = 0x7C as u8;
-        test[34] = 0xD6 as u8; test[35] = 0xFE as u8;
-        test[36] = 0x54 as u8; test[37] = 0xAA as u8;
-
-        chip8.load(test);
-
-        chip8.run();
-        assert_eq!(chip8.display[10][12], true);
-        assert_eq!(chip8.display[11][12], false);
-        assert_eq!(chip8.display[12][12], true);
-        assert_eq!(chip8.display[13][12], true);
-        assert_eq!(chip8.display[14][12], true);
-        assert_eq!(chip8.display[15][12], false);
-        assert_eq!(chip8.display[16][12], true);
-        assert_eq!(chip8.display[17][12], false);
-        assert_eq!(chip8.display[10][13], false);
-        assert_eq!(chip8.display[11][13], true);
-        assert_eq!(chip8.display[12][13], true);
-        assert_eq!(chip8.display[13][13], true);
-        assert_eq!(chip8.display[14][13], true);
-        assert_eq!(chip8.display[15][13], true);
-        assert_eq!(chip8.display[16][13], false);
-        assert_eq!(chip8.display[17][13], false);
-        assert_eq!(chip8.display[10][14], true);
-        assert_eq!(chip8.display[11][14], true);
-        assert_eq!(chip8.display[12][14], false);
-        assert_eq!(chip8.display[13][14], true);
-        assert_eq!(chip8.display[14][14], false);
-        assert_eq!(chip8.display[15][14], true);
-        assert_eq!(chip8.display[16][14], true);
-        assert_eq!(chip8.display[17][14], false);
-        assert_eq!(chip8.display[10][15], true);
-        assert_eq!(chip8.display[11][15], true);
-        assert_eq!(chip8.display[12][15], true);
-        assert_eq!(chip8.display[13][15], true);
-        assert_eq!(chip8.display[14][15], true);
-        assert_eq!(chip8.display[15][15], true);
-        assert_eq!(chip8.display[16][15], true);
-        assert_eq!(chip8.display[17][15], false);
-        assert_eq!(chip8.display[10][16], false);
-        assert_eq!(chip8.display[11][16], true);
-        assert_eq!(chip8.display[12][16], false);
-        assert_eq!(chip8.display[13][16], true);
-        assert_eq!(chip8.display[14][16], false);
-        assert_eq!(chip8.display[15][16], true);
-        assert_eq!(chip8.display[16][16], false);
-        assert_eq!(chip8.display[17][16], false);
-        assert_eq!(chip8.display[10][17], true);
-        assert_eq!(chip8.display[11][17], false);
-        assert_eq!(chip8.display[12][17], true);
-        assert_eq!(chip8.display[13][17], false);
-        assert_eq!(chip8.display[14][17], true);
-        assert_eq!(chip8.display[15][17], false);
-        assert_eq!(chip8.display[16][17], true);
-        assert_eq!(chip8.display[17][17], false);
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V2, 0x0a
+            LD V3, 0x0c
+            LD I, sprite
+            DRW V2, V3, 6
+            EXIT
+        sprite:
+            DB 0xBA, 0x7C, 0xD6, 0xFE, 0x54, 0xAA
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.display[0][10][12], true);
+        assert_eq!(chip8.display[0][11][12], false);
+        assert_eq!(chip8.display[0][12][12], true);
+        assert_eq!(chip8.display[0][13][12], true);
+        assert_eq!(chip8.display[0][14][12], true);
+        assert_eq!(chip8.display[0][15][12], false);
+        assert_eq!(chip8.display[0][16][12], true);
+        assert_eq!(chip8.display[0][17][12], false);
+        assert_eq!(chip8.display[0][10][13], false);
+        assert_eq!(chip8.display[0][11][13], true);
+        assert_eq!(chip8.display[0][12][13], true);
+        assert_eq!(chip8.display[0][13][13], true);
+        assert_eq!(chip8.display[0][14][13], true);
+        assert_eq!(chip8.display[0][15][13], true);
+        assert_eq!(chip8.display[0][16][13], false);
+        assert_eq!(chip8.display[0][17][13], false);
+        assert_eq!(chip8.display[0][10][14], true);
+        assert_eq!(chip8.display[0][11][14], true);
+        assert_eq!(chip8.display[0][12][14], false);
+        assert_eq!(chip8.display[0][13][14], true);
+        assert_eq!(chip8.display[0][14][14], false);
+        assert_eq!(chip8.display[0][15][14], true);
+        assert_eq!(chip8.display[0][16][14], true);
+        assert_eq!(chip8.display[0][17][14], false);
+        assert_eq!(chip8.display[0][10][15], true);
+        assert_eq!(chip8.display[0][11][15], true);
+        assert_eq!(chip8.display[0][12][15], true);
+        assert_eq!(chip8.display[0][13][15], true);
+        assert_eq!(chip8.display[0][14][15], true);
+        assert_eq!(chip8.display[0][15][15], true);
+        assert_eq!(chip8.display[0][16][15], true);
+        assert_eq!(chip8.display[0][17][15], false);
+        assert_eq!(chip8.display[0][10][16], false);
+        assert_eq!(chip8.display[0][11][16], true);
+        assert_eq!(chip8.display[0][12][16], false);
+        assert_eq!(chip8.display[0][13][16], true);
+        assert_eq!(chip8.display[0][14][16], false);
+        assert_eq!(chip8.display[0][15][16], true);
+        assert_eq!(chip8.display[0][16][16], false);
+        assert_eq!(chip8.display[0][17][16], false);
+        assert_eq!(chip8.display[0][10][17], true);
+        assert_eq!(chip8.display[0][11][17], false);
+        assert_eq!(chip8.display[0][12][17], true);
+        assert_eq!(chip8.display[0][13][17], false);
+        assert_eq!(chip8.display[0][14][17], true);
+        assert_eq!(chip8.display[0][15][17], false);
+        assert_eq!(chip8.display[0][16][17], true);
+        assert_eq!(chip8.display[0][17][17], false);
+    }
+
+    #[test]
+    fn test_sprite_clips_at_edge() {
+        let mut chip8 = CPU::new(Quirks { clip_sprites: true, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD V0, 60
+            LD V1, 31
+            LD I, sprite
+            DRW V0, V1, 2
+            EXIT
+        sprite:
+            DB 0xFF, 0xFF
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][63][31]);
+        assert!(!chip8.display[0][0][31]);
+        assert!(!chip8.display[0][60][0]);
+    }
+
+    #[test]
+    fn test_sprite_wraps_at_edge() {
+        let mut chip8 = CPU::new(Quirks { clip_sprites: false, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD V0, 60
+            LD V1, 31
+            LD I, sprite
+            DRW V0, V1, 2
+            EXIT
+        sprite:
+            DB 0xFF, 0xFF
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][63][31]);
+        assert!(chip8.display[0][3][31]);
+        assert!(chip8.display[0][60][0]);
+        assert!(chip8.display[0][3][0]);
+        assert!(!chip8.display[0][4][0]);
+    }
+
+    #[test]
+    fn test_sprite_collision_only_on_erase() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[15] = 1;
+
+        chip8.load_asm("
+            LD I, sprite
+            DRW V0, V1, 1
+            EXIT
+        sprite:
+            DB 0b10100000
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 0);
+        assert!(chip8.display[0][0][0]);
+        assert!(!chip8.display[0][1][0]);
+        assert!(chip8.display[0][2][0]);
+    }
+
+    #[test]
+    fn test_sprite_redraw_erases_and_collides() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, sprite
+            DRW V0, V1, 1
+            DRW V0, V1, 1
+            EXIT
+        sprite:
+            DB 0b10100000
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 1);
+        assert!(!chip8.display[0][0][0]);
+        assert!(!chip8.display[0][2][0]);
+    }
+
+    #[test]
+    fn test_sprite_start_position_wraps() {
+        let mut chip8 = CPU::new(Quirks { clip_sprites: true, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD V0, 70
+            LD V1, 33
+            LD I, sprite
+            DRW V0, V1, 1
+            EXIT
+        sprite:
+            DB 0b10000000
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][6][1]);
+    }
+
+    #[test]
+    fn test_switch_resolution() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][0] = true;
+
+        chip8.load_asm("
+            HIGH
+            LD V0, 100
+            LD V1, 50
+            LD I, sprite
+            DRW V0, V1, 1
+            EXIT
+        sprite:
+            DB 0b10000000
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.is_hires());
+        assert_eq!(chip8.display_width(), 128);
+        assert_eq!(chip8.display_height(), 64);
+        assert!(!chip8.display[0][0][0]);
+        assert!(chip8.display[0][100][50]);
+        assert_eq!(chip8.render_text().lines().count(), 64);
+
+        chip8.load_asm("
+            LOW
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.is_hires());
+        assert_eq!(chip8.display_width(), 64);
+        assert!(!chip8.display[0][100][50]);
+    }
+
+    #[test]
+    fn test_draw_large_sprite() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            HIGH
+            LD I, sprite
+            DRW V0, V0, 0
+            EXIT
+        sprite:
+            DW 0x8001, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFFFF
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][0][0]);
+        assert!(!chip8.display[0][1][0]);
+        assert!(chip8.display[0][15][0]);
+        assert!(!chip8.display[0][16][0]);
+        assert!(!chip8.display[0][0][1]);
+        assert!(chip8.display[0][8][15]);
+        assert_eq!(chip8.registers[15], 0);
+    }
+
+    #[test]
+    fn test_large_sprite_unsupported_on_chip8() {
+        let mut chip8 = CPU::with_variant(&Chip8);
+
+        chip8.load_asm("
+            DRW V0, V1, 0
+            EXIT
+        ");
+
+        assert_eq!(chip8.run(), Err(Chip8Error::UnsupportedOpcode { opcode: 0xD010, addr: 0x200, variant: "chip8" }));
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][5][0] = true;
+        chip8.display[0][5][30] = true;
+
+        chip8.load_asm("
+            SCD 3
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.display[0][5][0]);
+        assert!(chip8.display[0][5][3]);
+        assert!(!chip8.display[0][5][30]);
+        assert!(!chip8.display[0][5][33]);
+    }
+
+    #[test]
+    fn test_scroll_right_and_left() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][7] = true;
+        chip8.display[0][62][8] = true;
+
+        chip8.load_asm("
+            SCR
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.display[0][0][7]);
+        assert!(chip8.display[0][4][7]);
+        assert!(!chip8.display[0][62][8]);
+        assert!(!chip8.display[0][66][8]);
+
+        chip8.load_asm("
+            SCL
+            SCL
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.display[0][4][7]);
+        assert!(!chip8.display[0][0][7]);
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][5][0] = true;
+        chip8.display[0][5][10] = true;
+
+        chip8.load_asm("
+            SCU 4
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][5][6]);
+        assert!(!chip8.display[0][5][10]);
+        assert!(!chip8.display[0][5][0]);
+    }
+
+    #[test]
+    fn test_draw_to_selected_planes() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            PLANE 2
+            LD I, sprite
+            DRW V0, V0, 1
+            PLANE 3
+            LD V1, 8
+            DRW V1, V0, 1
+            PLANE 0
+            DRW V0, V1, 1
+            EXIT
+        sprite:
+            DB 0b10000000, 0b01000000
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.selected_planes(), 0);
+        assert!(!chip8.display[0][0][0]);
+        assert!(chip8.display[1][0][0]);
+        assert!(chip8.display[0][8][0]);
+        assert!(!chip8.display[1][8][0]);
+        assert!(chip8.display[1][9][0]);
+        assert!(!chip8.display[0][0][8]);
+        assert!(!chip8.display[1][0][8]);
+        assert!(chip8.render_text().starts_with("#.......##"));
+    }
+
+    #[test]
+    fn test_collision_in_any_plane() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[1][0][0] = true;
+
+        chip8.load_asm("
+            PLANE 3
+            LD I, sprite
+            DRW V0, V0, 1
+            EXIT
+        sprite:
+            DB 0b10000000, 0b10000000
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 1);
+        assert!(chip8.display[0][0][0]);
+        assert!(!chip8.display[1][0][0]);
+    }
+
+    #[test]
+    fn test_clear_selected_planes() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][0] = true;
+        chip8.display[1][0][0] = true;
+
+        chip8.load_asm("
+            PLANE 2
+            CLS
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.display[0][0][0]);
+        assert!(!chip8.display[1][0][0]);
     }
 }
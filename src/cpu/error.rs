@@ -0,0 +1,33 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Chip8Error {
+    UnknownOpcode { opcode: u16, addr: usize },
+    UnsupportedOpcode { opcode: u16, addr: usize, variant: &'static str },
+    StackOverflow { addr: usize },
+    StackUnderflow { addr: usize },
+    PcOutOfBounds { addr: usize },
+    MemoryOutOfBounds { addr: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, addr } =>
+                write!(f, "Unknown opcode {:04x} at {:04x}", opcode, addr),
+            Chip8Error::UnsupportedOpcode { opcode, addr, variant } =>
+                write!(f, "Opcode {:04x} at {:04x} is not supported by {}", opcode, addr, variant),
+            Chip8Error::StackOverflow { addr } =>
+                write!(f, "Stack overflow calling a subroutine at {:04x}", addr),
+            Chip8Error::StackUnderflow { addr } =>
+                write!(f, "Stack underflow returning from a subroutine at {:04x}", addr),
+            Chip8Error::PcOutOfBounds { addr } =>
+                write!(f, "Program counter {:04x} is outside of memory", addr),
+            Chip8Error::MemoryOutOfBounds { addr } =>
+                write!(f, "Memory access at {:04x} is outside of memory", addr),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
@@ -0,0 +1,192 @@
+use super::{ CPU, FONT_START_ADDR, FONT_SIZE, LARGE_FONT_START_ADDR, LARGE_FONT_SIZE, PROGRAM_START_ADDR };
+
+use std::error::Error;
+use std::fmt;
+
+pub const STANDARD_GLYPHS: [u8; FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The digits as drawn by the original COSMAC VIP interpreter.
+pub const VIP_GLYPHS: [u8; FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The 8x10 SUPER-CHIP digits used by Fx30, extended with A-F.
+pub const LARGE_GLYPHS: [u8; LARGE_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontError {
+    OverlapsProgram { addr: usize },
+    OverlapsLargeFont { addr: usize },
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::OverlapsProgram { addr } =>
+                write!(f, "Font at {:04x} overlaps the program area at {:04x}", addr, PROGRAM_START_ADDR),
+            FontError::OverlapsLargeFont { addr } =>
+                write!(f, "Font at {:04x} overlaps the large font at {:04x}", addr, LARGE_FONT_START_ADDR),
+        }
+    }
+}
+
+impl Error for FontError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Font {
+    addr: usize,
+    glyphs: [u8; FONT_SIZE],
+}
+
+impl Font {
+    /// Fails if the glyphs would not fit below the program start address,
+    /// or would overwrite the large digits used by Fx30.
+    pub fn new(addr: usize, glyphs: [u8; FONT_SIZE]) -> Result<Self, FontError> {
+        if addr.checked_add(FONT_SIZE).is_none_or(|end| end > PROGRAM_START_ADDR) {
+            return Err(FontError::OverlapsProgram { addr });
+        }
+
+        if addr < LARGE_FONT_START_ADDR + LARGE_FONT_SIZE && LARGE_FONT_START_ADDR < addr + FONT_SIZE {
+            return Err(FontError::OverlapsLargeFont { addr });
+        }
+
+        Ok(Font { addr, glyphs })
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn glyphs(&self) -> &[u8; FONT_SIZE] {
+        &self.glyphs
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font { addr: FONT_START_ADDR, glyphs: STANDARD_GLYPHS }
+    }
+}
+
+impl CPU {
+    pub fn set_font(&mut self, font: &Font) {
+        let glyphs = font.glyphs();
+
+        self.memory[self.font_addr..self.font_addr + FONT_SIZE].iter_mut().for_each(|b| *b = 0);
+        self.memory[font.addr()..font.addr() + FONT_SIZE].copy_from_slice(glyphs);
+        self.font_addr = font.addr();
+    }
+
+    pub fn font_addr(&self) -> usize {
+        self.font_addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_installed_on_new() {
+        let chip8 = CPU::default();
+
+        assert_eq!(chip8.font_addr(), FONT_START_ADDR);
+        assert_eq!(&chip8.memory[FONT_START_ADDR..FONT_START_ADDR + FONT_SIZE], &STANDARD_GLYPHS[..]);
+    }
+
+    #[test]
+    fn test_set_font_moves_glyphs() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_font(&Font::new(0x000, VIP_GLYPHS).unwrap());
+
+        assert_eq!(chip8.font_addr(), 0x000);
+        assert_eq!(&chip8.memory[0x000..FONT_SIZE], &VIP_GLYPHS[..]);
+        assert_eq!(chip8.memory[FONT_SIZE], 0);
+    }
+
+    #[test]
+    fn test_font_overlapping_program() {
+        assert_eq!(Font::new(0x1D0, STANDARD_GLYPHS), Err(FontError::OverlapsProgram { addr: 0x1D0 }));
+        assert!(Font::new(0x1B0, STANDARD_GLYPHS).is_ok());
+        assert_eq!(Font::new(usize::MAX, STANDARD_GLYPHS), Err(FontError::OverlapsProgram { addr: usize::MAX }));
+    }
+
+    #[test]
+    fn test_font_overlapping_large_font() {
+        assert_eq!(Font::new(0x100, STANDARD_GLYPHS), Err(FontError::OverlapsLargeFont { addr: 0x100 }));
+        assert_eq!(Font::new(0x060, STANDARD_GLYPHS), Err(FontError::OverlapsLargeFont { addr: 0x060 }));
+        assert!(Font::new(0x140, STANDARD_GLYPHS).is_ok());
+    }
+
+    #[test]
+    fn test_draw_font_digit() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 0x7;
+
+        chip8.load_asm("
+            LD F, V0
+            DRW V1, V1, 5
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i as usize, FONT_START_ADDR + 0x7 * 5);
+        assert!(chip8.display[0][0][0]);
+        assert!(chip8.display[0][3][0]);
+        assert!(!chip8.display[0][2][1]);
+        assert!(chip8.display[0][3][1]);
+        assert!(chip8.display[0][1][4]);
+        assert!(!chip8.display[0][3][4]);
+    }
+}
@@ -0,0 +1,228 @@
+use super::{ CPU, Plane, PLANE_COUNT, HIRES_DISPLAY_HEIGHT };
+
+/// RGBA colours for each combination of lit planes, indexed by plane mask:
+/// neither, plane 1, plane 2, both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; 1 << PLANE_COUNT],
+}
+
+/// Names accepted by `Palette::preset`.
+pub const PALETTE_PRESETS: [&str; 2] = ["mono", "octo"];
+
+impl Palette {
+    /// Black background with white pixels, for single plane programs.
+    pub const MONOCHROME: Palette = Palette {
+        colors: [[0x00, 0x00, 0x00, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF]],
+    };
+
+    /// The default colours used by Octo.
+    pub const OCTO: Palette = Palette {
+        colors: [[0x99, 0x66, 0x00, 0xFF], [0xFF, 0xCC, 0x00, 0xFF], [0xFF, 0x66, 0x00, 0xFF], [0x66, 0x22, 0x00, 0xFF]],
+    };
+
+    /// Looks up a palette by one of the names in `PALETTE_PRESETS`.
+    pub fn preset(name: &str) -> Option<Palette> {
+        match name.to_ascii_lowercase().as_str() {
+            "mono" => Some(Palette::MONOCHROME),
+            "octo" => Some(Palette::OCTO),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::MONOCHROME
+    }
+}
+
+/// A read-only view of the display at its current resolution.
+#[derive(Clone, Copy)]
+pub struct Framebuffer<'a> {
+    display: &'a [Plane; PLANE_COUNT],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Framebuffer<'a> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Bitmask of the planes lit at (x, y).
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.display.iter().enumerate()
+            .filter(|(_, plane)| plane[x][y])
+            .fold(0, |mask, (n, _)| mask | 1 << n)
+    }
+
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    /// One bit per pixel, rows top to bottom, leftmost pixel in the most
+    /// significant bit. A pixel is set if it is lit in any plane.
+    pub fn to_1bpp(&self) -> Vec<u8> {
+        let row_bytes = self.width.div_ceil(8);
+        let mut packed = vec![0; row_bytes * self.height];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_lit(x, y) {
+                    packed[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+
+        packed
+    }
+
+    /// Four bytes per pixel, rows top to bottom, coloured by `palette`.
+    pub fn to_rgba8(&self, palette: &Palette) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.width * self.height * 4);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                pixels.extend_from_slice(&palette.colors[self.pixel(x, y) as usize]);
+            }
+        }
+
+        pixels
+    }
+}
+
+impl CPU {
+    pub fn framebuffer(&self) -> Framebuffer<'_> {
+        Framebuffer { display: &self.display, width: self.display_width(), height: self.display_height() }
+    }
+
+    /// Whether anything on screen changed since the last `clear_dirty`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_rows.iter().any(|&dirty| dirty)
+    }
+
+    /// Rows that changed since the last `clear_dirty`, top to bottom.
+    pub fn dirty_rows(&self) -> Vec<usize> {
+        (0..self.display_height()).filter(|&y| self.dirty_rows[y]).collect()
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rows = [false; HIRES_DISPLAY_HEIGHT];
+    }
+
+    pub(super) fn mark_dirty(&mut self, y: usize) {
+        self.dirty_rows[y] = true;
+    }
+
+    pub(super) fn mark_all_dirty(&mut self) {
+        self.dirty_rows = [true; HIRES_DISPLAY_HEIGHT];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framebuffer_geometry() {
+        let mut chip8 = CPU::default();
+
+        assert_eq!(chip8.framebuffer().width(), 64);
+        assert_eq!(chip8.framebuffer().height(), 32);
+
+        chip8.load_asm("
+            HIGH
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.framebuffer().width(), 128);
+        assert_eq!(chip8.framebuffer().height(), 64);
+    }
+
+    #[test]
+    fn test_framebuffer_to_1bpp() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][0][0] = true;
+        chip8.display[1][9][0] = true;
+        chip8.display[0][63][31] = true;
+
+        let packed = chip8.framebuffer().to_1bpp();
+        assert_eq!(packed.len(), 8 * 32);
+        assert_eq!(packed[0], 0x80);
+        assert_eq!(packed[1], 0x40);
+        assert_eq!(packed[8 * 31 + 7], 0x01);
+        assert_eq!(packed.iter().filter(|&&b| b != 0).count(), 3);
+    }
+
+    #[test]
+    fn test_framebuffer_to_rgba8() {
+        let mut chip8 = CPU::default();
+
+        chip8.display[0][1][0] = true;
+        chip8.display[1][2][0] = true;
+        chip8.display[0][3][0] = true;
+        chip8.display[1][3][0] = true;
+
+        let framebuffer = chip8.framebuffer();
+        assert_eq!(framebuffer.pixel(3, 0), 3);
+
+        let pixels = framebuffer.to_rgba8(&Palette::OCTO);
+        assert_eq!(pixels.len(), 64 * 32 * 4);
+        assert_eq!(&pixels[0..4], &Palette::OCTO.colors[0]);
+        assert_eq!(&pixels[4..8], &Palette::OCTO.colors[1]);
+        assert_eq!(&pixels[8..12], &Palette::OCTO.colors[2]);
+        assert_eq!(&pixels[12..16], &Palette::OCTO.colors[3]);
+    }
+
+    #[test]
+    fn test_palette_presets() {
+        for name in PALETTE_PRESETS.iter() {
+            assert!(Palette::preset(name).is_some(), "{}", name);
+        }
+
+        assert_eq!(Palette::preset("OCTO"), Some(Palette::OCTO));
+        assert_eq!(Palette::preset("sepia"), None);
+    }
+
+    #[test]
+    fn test_draw_marks_rows_dirty() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V1, 4
+            LD I, sprite
+            DRW V0, V1, 3
+            EXIT
+        sprite:
+            DB 0x80, 0x00, 0x80
+        ");
+        assert!(!chip8.is_dirty());
+
+        chip8.run().unwrap();
+        assert!(chip8.is_dirty());
+        assert_eq!(chip8.dirty_rows(), vec![4, 6]);
+
+        chip8.clear_dirty();
+        assert!(!chip8.is_dirty());
+    }
+
+    #[test]
+    fn test_clear_marks_all_rows_dirty() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            CLS
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.dirty_rows(), (0..32).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,369 @@
+use super::*;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    Clear,
+    Return,
+    Exit,
+    ScrollDown { n: u8 },
+    ScrollUp { n: u8 },
+    ScrollRight,
+    ScrollLeft,
+    LowRes,
+    HighRes,
+    /// 0nnn. Addresses such as 0x0E0 encode to another 00xx instruction,
+    /// which the assemblers reject.
+    Sys { addr: u16 },
+    Jump { addr: u16 },
+    Call { addr: u16 },
+    SkipIfEqual { x: u8, byte: u8 },
+    SkipIfNotEqual { x: u8, byte: u8 },
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    StoreRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    Store { x: u8, byte: u8 },
+    AddValue { x: u8, byte: u8 },
+    Copy { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    Add { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    ShiftRight { x: u8, y: u8 },
+    Subn { x: u8, y: u8 },
+    ShiftLeft { x: u8, y: u8 },
+    StoreI { addr: u16 },
+    /// F000, followed by the 16-bit address to load into I. This is the only
+    /// two-word instruction: it covers the first word alone, so it displays
+    /// as `LD I, LONG` and the address has to be appended to assemble it.
+    StoreILong,
+    JumpV0 { addr: u16 },
+    Random { x: u8, byte: u8 },
+    Draw { x: u8, y: u8, n: u8 },
+    SkipIfKeyPressed { x: u8 },
+    SkipIfKeyNotPressed { x: u8 },
+    SelectPlanes { n: u8 },
+    LoadAudioPattern,
+    SetPitch { x: u8 },
+    LoadDelayTimer { x: u8 },
+    LoadKeyPress { x: u8 },
+    SetDelayTimer { x: u8 },
+    SetSoundTimer { x: u8 },
+    AddToI { x: u8 },
+    LoadSpriteAddr { x: u8 },
+    LoadLargeSpriteAddr { x: u8 },
+    StoreBcd { x: u8 },
+    StoreRegisters { x: u8 },
+    LoadRegisters { x: u8 },
+    StoreFlags { x: u8 },
+    LoadFlags { x: u8 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown opcode {:04x}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+fn join(op_code: u8, x: u8, y: u8, value: u8) -> u16 {
+    (op_code as u16) << 12 | ((x & 0xF) as u16) << 8 | ((y & 0xF) as u16) << 4 | (value & 0xF) as u16
+}
+
+fn join_byte(op_code: u8, x: u8, byte: u8) -> u16 {
+    (op_code as u16) << 12 | ((x & 0xF) as u16) << 8 | byte as u16
+}
+
+fn join_addr(op_code: u8, addr: u16) -> u16 {
+    (op_code as u16) << 12 | (addr & 0x0FFF)
+}
+
+impl Instruction {
+    pub fn decode(op: u16) -> Result<Instruction, DecodeError> {
+        let op_code = ((op & 0xF000) >> 12) as u8;
+        let x = ((op & 0x0F00) >> 8) as u8;
+        let y = ((op & 0x00F0) >> 4) as u8;
+        let value = (op & 0x000F) as u8;
+        let addr = op & 0x0FFF;
+        let byte = (op & 0x00FF) as u8;
+
+        let unknown = Err(DecodeError { opcode: op });
+
+        let instruction = match op_code {
+            MISC => {
+                match addr {
+                    CLEAR_SCREEN => Instruction::Clear,
+                    ENDROUTINE => Instruction::Return,
+                    EXIT => Instruction::Exit,
+                    SCROLL_RIGHT => Instruction::ScrollRight,
+                    SCROLL_LEFT => Instruction::ScrollLeft,
+                    LOW_RES => Instruction::LowRes,
+                    HIGH_RES => Instruction::HighRes,
+                    _ if addr & 0xFF0 == SCROLL_DOWN => Instruction::ScrollDown { n: value },
+                    _ if addr & 0xFF0 == SCROLL_UP => Instruction::ScrollUp { n: value },
+                    _ => Instruction::Sys { addr },
+                }
+            },
+            JUMP => Instruction::Jump { addr },
+            SUBROUTINE => Instruction::Call { addr },
+            SKIP_IF_EQUAL => Instruction::SkipIfEqual { x, byte },
+            SKIP_IF_NOT_EQUAL => Instruction::SkipIfNotEqual { x, byte },
+            SKIP_IF_REGISTER_EQUAL if value == 0 => Instruction::SkipIfRegistersEqual { x, y },
+            SKIP_IF_REGISTER_EQUAL if value == STORE_REGISTER_RANGE => Instruction::StoreRange { x, y },
+            SKIP_IF_REGISTER_EQUAL if value == LOAD_REGISTER_RANGE => Instruction::LoadRange { x, y },
+            SKIP_IF_REGISTER_NOT_EQUAL if value == 0 => Instruction::SkipIfRegistersNotEqual { x, y },
+            STORE_VALUE_TO_REGISTER => Instruction::Store { x, byte },
+            ADD_VALUE_TO_REGISTER => Instruction::AddValue { x, byte },
+            REGISTER_OPERATION => {
+                match value {
+                    REGISTER_STORE => Instruction::Copy { x, y },
+                    REGISTER_OR => Instruction::Or { x, y },
+                    REGISTER_AND => Instruction::And { x, y },
+                    REGISTER_XOR => Instruction::Xor { x, y },
+                    REGISTER_ADD => Instruction::Add { x, y },
+                    REGISTER_SUB => Instruction::Sub { x, y },
+                    REGISTER_SHIFT_RIGHT => Instruction::ShiftRight { x, y },
+                    REGISTER_SUBN => Instruction::Subn { x, y },
+                    REGISTER_SHIFT_LEFT => Instruction::ShiftLeft { x, y },
+                    _ => return unknown,
+                }
+            },
+            STORE_ADDR_I => Instruction::StoreI { addr },
+            JUMP_ADDR_PLUS_V0 => Instruction::JumpV0 { addr },
+            RANDOM_AND => Instruction::Random { x, byte },
+            DISPLAY => Instruction::Draw { x, y, n: value },
+            KEY_OPERATION => {
+                match byte {
+                    SKIP_IF_KEY_PRESSED => Instruction::SkipIfKeyPressed { x },
+                    SKIP_IF_KEY_NOT_PRESSED => Instruction::SkipIfKeyNotPressed { x },
+                    _ => return unknown,
+                }
+            },
+            LOAD_OPERATION => {
+                match byte {
+                    STORE_I_LONG if x == 0 => Instruction::StoreILong,
+                    SELECT_PLANES if x <= 3 => Instruction::SelectPlanes { n: x },
+                    LOAD_AUDIO_PATTERN if x == 0 => Instruction::LoadAudioPattern,
+                    SET_PITCH => Instruction::SetPitch { x },
+                    LOAD_DELAY_TIMER => Instruction::LoadDelayTimer { x },
+                    LOAD_KEY_PRESS => Instruction::LoadKeyPress { x },
+                    SET_DELAY_TIMER => Instruction::SetDelayTimer { x },
+                    SET_SOUND_TIMER => Instruction::SetSoundTimer { x },
+                    ADD_TO_I => Instruction::AddToI { x },
+                    LOAD_SPRITE_ADDR => Instruction::LoadSpriteAddr { x },
+                    LOAD_LARGE_SPRITE_ADDR => Instruction::LoadLargeSpriteAddr { x },
+                    STORE_BCD => Instruction::StoreBcd { x },
+                    STORE_REGISTERS => Instruction::StoreRegisters { x },
+                    LOAD_REGISTERS => Instruction::LoadRegisters { x },
+                    STORE_FLAGS => Instruction::StoreFlags { x },
+                    LOAD_FLAGS => Instruction::LoadFlags { x },
+                    _ => return unknown,
+                }
+            },
+            _ => return unknown,
+        };
+
+        Ok(instruction)
+    }
+
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Clear => join_addr(MISC, CLEAR_SCREEN),
+            Instruction::Return => join_addr(MISC, ENDROUTINE),
+            Instruction::Exit => join_addr(MISC, EXIT),
+            Instruction::ScrollDown { n } => join_addr(MISC, SCROLL_DOWN | (n & 0xF) as u16),
+            Instruction::ScrollUp { n } => join_addr(MISC, SCROLL_UP | (n & 0xF) as u16),
+            Instruction::ScrollRight => join_addr(MISC, SCROLL_RIGHT),
+            Instruction::ScrollLeft => join_addr(MISC, SCROLL_LEFT),
+            Instruction::LowRes => join_addr(MISC, LOW_RES),
+            Instruction::HighRes => join_addr(MISC, HIGH_RES),
+            Instruction::Sys { addr } => join_addr(MISC, addr),
+            Instruction::Jump { addr } => join_addr(JUMP, addr),
+            Instruction::Call { addr } => join_addr(SUBROUTINE, addr),
+            Instruction::SkipIfEqual { x, byte } => join_byte(SKIP_IF_EQUAL, x, byte),
+            Instruction::SkipIfNotEqual { x, byte } => join_byte(SKIP_IF_NOT_EQUAL, x, byte),
+            Instruction::SkipIfRegistersEqual { x, y } => join(SKIP_IF_REGISTER_EQUAL, x, y, 0),
+            Instruction::SkipIfRegistersNotEqual { x, y } => join(SKIP_IF_REGISTER_NOT_EQUAL, x, y, 0),
+            Instruction::StoreRange { x, y } => join(SKIP_IF_REGISTER_EQUAL, x, y, STORE_REGISTER_RANGE),
+            Instruction::LoadRange { x, y } => join(SKIP_IF_REGISTER_EQUAL, x, y, LOAD_REGISTER_RANGE),
+            Instruction::Store { x, byte } => join_byte(STORE_VALUE_TO_REGISTER, x, byte),
+            Instruction::AddValue { x, byte } => join_byte(ADD_VALUE_TO_REGISTER, x, byte),
+            Instruction::Copy { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_STORE),
+            Instruction::Or { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_OR),
+            Instruction::And { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_AND),
+            Instruction::Xor { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_XOR),
+            Instruction::Add { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_ADD),
+            Instruction::Sub { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_SUB),
+            Instruction::ShiftRight { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_SHIFT_RIGHT),
+            Instruction::Subn { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_SUBN),
+            Instruction::ShiftLeft { x, y } => join(REGISTER_OPERATION, x, y, REGISTER_SHIFT_LEFT),
+            Instruction::StoreI { addr } => join_addr(STORE_ADDR_I, addr),
+            Instruction::StoreILong => join_byte(LOAD_OPERATION, 0, STORE_I_LONG),
+            Instruction::JumpV0 { addr } => join_addr(JUMP_ADDR_PLUS_V0, addr),
+            Instruction::Random { x, byte } => join_byte(RANDOM_AND, x, byte),
+            Instruction::Draw { x, y, n } => join(DISPLAY, x, y, n),
+            Instruction::SkipIfKeyPressed { x } => join_byte(KEY_OPERATION, x, SKIP_IF_KEY_PRESSED),
+            Instruction::SkipIfKeyNotPressed { x } => join_byte(KEY_OPERATION, x, SKIP_IF_KEY_NOT_PRESSED),
+            Instruction::SelectPlanes { n } => join_byte(LOAD_OPERATION, n, SELECT_PLANES),
+            Instruction::LoadAudioPattern => join_byte(LOAD_OPERATION, 0, LOAD_AUDIO_PATTERN),
+            Instruction::SetPitch { x } => join_byte(LOAD_OPERATION, x, SET_PITCH),
+            Instruction::LoadDelayTimer { x } => join_byte(LOAD_OPERATION, x, LOAD_DELAY_TIMER),
+            Instruction::LoadKeyPress { x } => join_byte(LOAD_OPERATION, x, LOAD_KEY_PRESS),
+            Instruction::SetDelayTimer { x } => join_byte(LOAD_OPERATION, x, SET_DELAY_TIMER),
+            Instruction::SetSoundTimer { x } => join_byte(LOAD_OPERATION, x, SET_SOUND_TIMER),
+            Instruction::AddToI { x } => join_byte(LOAD_OPERATION, x, ADD_TO_I),
+            Instruction::LoadSpriteAddr { x } => join_byte(LOAD_OPERATION, x, LOAD_SPRITE_ADDR),
+            Instruction::LoadLargeSpriteAddr { x } => join_byte(LOAD_OPERATION, x, LOAD_LARGE_SPRITE_ADDR),
+            Instruction::StoreBcd { x } => join_byte(LOAD_OPERATION, x, STORE_BCD),
+            Instruction::StoreRegisters { x } => join_byte(LOAD_OPERATION, x, STORE_REGISTERS),
+            Instruction::LoadRegisters { x } => join_byte(LOAD_OPERATION, x, LOAD_REGISTERS),
+            Instruction::StoreFlags { x } => join_byte(LOAD_OPERATION, x, STORE_FLAGS),
+            Instruction::LoadFlags { x } => join_byte(LOAD_OPERATION, x, LOAD_FLAGS),
+        }
+    }
+
+    /// The earliest instruction set that includes this instruction.
+    pub fn instruction_set(&self) -> InstructionSet {
+        match *self {
+            Instruction::Exit |
+            Instruction::ScrollDown { .. } |
+            Instruction::ScrollRight |
+            Instruction::ScrollLeft |
+            Instruction::LowRes |
+            Instruction::HighRes |
+            Instruction::Draw { n: 0, .. } |
+            Instruction::LoadLargeSpriteAddr { .. } |
+            Instruction::StoreFlags { .. } |
+            Instruction::LoadFlags { .. } => InstructionSet::SuperChip,
+            Instruction::ScrollUp { .. } |
+            Instruction::StoreRange { .. } |
+            Instruction::LoadRange { .. } |
+            Instruction::StoreILong |
+            Instruction::SelectPlanes { .. } |
+            Instruction::LoadAudioPattern |
+            Instruction::SetPitch { .. } => InstructionSet::XoChip,
+            _ => InstructionSet::Chip8,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Sys { addr } => write!(f, "SYS {:#05x}", addr),
+            Instruction::Jump { addr } => write!(f, "JP {:#05x}", addr),
+            Instruction::Call { addr } => write!(f, "CALL {:#05x}", addr),
+            Instruction::SkipIfEqual { x, byte } => write!(f, "SE V{:X}, {:#04x}", x, byte),
+            Instruction::SkipIfNotEqual { x, byte } => write!(f, "SNE V{:X}, {:#04x}", x, byte),
+            Instruction::SkipIfRegistersEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::Store { x, byte } => write!(f, "LD V{:X}, {:#04x}", x, byte),
+            Instruction::AddValue { x, byte } => write!(f, "ADD V{:X}, {:#04x}", x, byte),
+            Instruction::Copy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::StoreI { addr } => write!(f, "LD I, {:#05x}", addr),
+            Instruction::StoreILong => write!(f, "LD I, LONG"),
+            Instruction::JumpV0 { addr } => write!(f, "JP V0, {:#05x}", addr),
+            Instruction::Random { x, byte } => write!(f, "RND V{:X}, {:#04x}", x, byte),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::LoadDelayTimer { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::LoadKeyPress { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddToI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadSpriteAddr { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadLargeSpriteAddr { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_fields() {
+        assert_eq!(Instruction::decode(0x00E0), Ok(Instruction::Clear));
+        assert_eq!(Instruction::decode(0x00EE), Ok(Instruction::Return));
+        assert_eq!(Instruction::decode(0x00FD), Ok(Instruction::Exit));
+        assert_eq!(Instruction::decode(0x0123), Ok(Instruction::Sys { addr: 0x123 }));
+        assert_eq!(Instruction::decode(0x00C4), Ok(Instruction::ScrollDown { n: 4 }));
+        assert_eq!(Instruction::decode(0x00FF), Ok(Instruction::HighRes));
+        assert_eq!(Instruction::decode(0xF385), Ok(Instruction::LoadFlags { x: 3 }));
+        assert_eq!(Instruction::decode(0x00D2), Ok(Instruction::ScrollUp { n: 2 }));
+        assert_eq!(Instruction::decode(0x5132), Ok(Instruction::StoreRange { x: 1, y: 3 }));
+        assert_eq!(Instruction::decode(0xF000), Ok(Instruction::StoreILong));
+        assert_eq!(Instruction::decode(0xF301), Ok(Instruction::SelectPlanes { n: 3 }));
+        assert_eq!(Instruction::decode(0xF002), Ok(Instruction::LoadAudioPattern));
+        assert_eq!(Instruction::decode(0x1ABC), Ok(Instruction::Jump { addr: 0xABC }));
+        assert_eq!(Instruction::decode(0x3A42), Ok(Instruction::SkipIfEqual { x: 0xA, byte: 0x42 }));
+        assert_eq!(Instruction::decode(0x8124), Ok(Instruction::Add { x: 1, y: 2 }));
+        assert_eq!(Instruction::decode(0xD12F), Ok(Instruction::Draw { x: 1, y: 2, n: 0xF }));
+        assert_eq!(Instruction::decode(0xE59E), Ok(Instruction::SkipIfKeyPressed { x: 5 }));
+        assert_eq!(Instruction::decode(0xF733), Ok(Instruction::StoreBcd { x: 7 }));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for &op in &[0x5121, 0x8128, 0x912F, 0xE100, 0xF1FF, 0xF401, 0xF102] {
+            assert_eq!(Instruction::decode(op), Err(DecodeError { opcode: op }));
+        }
+    }
+
+    #[test]
+    fn test_mnemonics() {
+        assert_eq!(Instruction::Clear.to_string(), "CLS");
+        assert_eq!(Instruction::Jump { addr: 0x20C }.to_string(), "JP 0x20c");
+        assert_eq!(Instruction::SkipIfEqual { x: 0xA, byte: 0x05 }.to_string(), "SE VA, 0x05");
+        assert_eq!(Instruction::Subn { x: 0, y: 1 }.to_string(), "SUBN V0, V1");
+        assert_eq!(Instruction::Draw { x: 2, y: 3, n: 6 }.to_string(), "DRW V2, V3, 6");
+        assert_eq!(Instruction::StoreRegisters { x: 0xF }.to_string(), "LD [I], VF");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for op in 0..=0xFFFFu16 {
+            if let Ok(instruction) = Instruction::decode(op) {
+                assert_eq!(instruction.encode(), op, "{:?}", instruction);
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::CPU;
+
+const KEY_COUNT: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Keypad {
+    keys: [bool; KEY_COUNT],
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Keypad { keys: [false; KEY_COUNT] }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys[(key & 0x0F) as usize] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.keys[(key & 0x0F) as usize] = false;
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [false; KEY_COUNT];
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0x0F) as usize]
+    }
+
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys.iter().position(|&pressed| pressed).map(|key| key as u8)
+    }
+}
+
+impl CPU {
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    /// Presses `key` on the hex keypad. If the program is suspended on Fx0A,
+    /// the key is stored in the waiting register and `run` can be resumed.
+    pub fn press_key(&mut self, key: u8) {
+        self.keypad.press(key);
+
+        if let Some(x) = self.waiting_for_key.take() {
+            self.registers[x] = key & 0x0F;
+        }
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.keypad.release(key);
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key.is_some()
+    }
+
+    pub(super) fn skip_if_key_pressed(&mut self, x: usize) {
+        if self.keypad.is_pressed(self.registers[x]) {
+            self.skip_instruction();
+        }
+    }
+
+    pub(super) fn skip_if_key_not_pressed(&mut self, x: usize) {
+        if !self.keypad.is_pressed(self.registers[x]) {
+            self.skip_instruction();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypad_press_and_release() {
+        let mut keypad = Keypad::new();
+        assert_eq!(keypad.first_pressed(), None);
+
+        keypad.press(0x4);
+        keypad.press(0xC);
+        assert!(keypad.is_pressed(0x4));
+        assert!(keypad.is_pressed(0xC));
+        assert!(!keypad.is_pressed(0x5));
+        assert_eq!(keypad.first_pressed(), Some(0x4));
+
+        keypad.release(0x4);
+        assert!(!keypad.is_pressed(0x4));
+        assert_eq!(keypad.first_pressed(), Some(0xC));
+
+        keypad.release_all();
+        assert_eq!(keypad.first_pressed(), None);
+    }
+
+    #[test]
+    fn test_skip_if_key_pressed() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 5;
+        chip8.registers[1] = 6;
+        chip8.registers[2] = 0xA;
+        chip8.press_key(0xA);
+
+        chip8.load_asm("
+            SKP V2
+            ADD V0, V1
+            SKP V1
+            ADD V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 11);
+    }
+
+    #[test]
+    fn test_skip_if_key_not_pressed() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 5;
+        chip8.registers[1] = 6;
+        chip8.registers[2] = 0xA;
+        chip8.press_key(0xA);
+
+        chip8.load_asm("
+            SKNP V1
+            ADD V0, V1
+            SKNP V2
+            ADD V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 11);
+    }
+
+    #[test]
+    fn test_wait_for_key_resumes_after_press() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V3, K
+            LD V1, 0x01
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.registers[1], 0);
+
+        chip8.run().unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.program_counter, 0x202);
+
+        chip8.press_key(0x7);
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.registers[3], 0x7);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[1], 1);
+    }
+}
@@ -0,0 +1,423 @@
+use super::{ CPU, Chip8Error, FONT_SPRITE_SIZE, LARGE_FONT_START_ADDR, LARGE_FONT_SPRITE_SIZE };
+
+impl CPU {
+    pub(super) fn load_delay_timer(&mut self, x: usize) {
+        self.registers[x] = self.delay_timer;
+    }
+
+    pub(super) fn load_key_press(&mut self, x: usize) {
+        match self.keypad.first_pressed() {
+            Some(key) => { self.registers[x] = key; },
+            None => { self.waiting_for_key = Some(x); },
+        }
+    }
+
+    pub(super) fn set_delay_timer(&mut self, x: usize) {
+        self.delay_timer = self.registers[x];
+    }
+
+    pub(super) fn set_sound_timer(&mut self, x: usize) {
+        self.sound_timer = self.registers[x];
+    }
+
+    pub(super) fn add_to_i(&mut self, x: usize) {
+        self.i = self.i.wrapping_add(self.registers[x] as u16);
+    }
+
+    pub(super) fn load_sprite_addr(&mut self, x: usize) {
+        let digit = (self.registers[x] & 0x0F) as usize;
+        self.i = (self.font_addr + digit * FONT_SPRITE_SIZE) as u16;
+    }
+
+    pub(super) fn load_large_sprite_addr(&mut self, x: usize) {
+        let digit = (self.registers[x] & 0x0F) as usize;
+        self.i = (LARGE_FONT_START_ADDR + digit * LARGE_FONT_SPRITE_SIZE) as u16;
+    }
+
+    pub(super) fn store_bcd(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let value = self.registers[x];
+        let addr = self.i as usize;
+        self.check_memory(addr, 3)?;
+
+        self.memory[addr] = value / 100;
+        self.memory[addr + 1] = (value / 10) % 10;
+        self.memory[addr + 2] = value % 10;
+
+        Ok(())
+    }
+
+    pub(super) fn store_registers(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        self.check_memory(addr, x + 1)?;
+
+        self.memory[addr..=addr + x].copy_from_slice(&self.registers[..=x]);
+        self.increment_i_after_transfer(x);
+        Ok(())
+    }
+
+    pub(super) fn load_registers(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        self.check_memory(addr, x + 1)?;
+
+        self.registers[..=x].copy_from_slice(&self.memory[addr..=addr + x]);
+        self.increment_i_after_transfer(x);
+        Ok(())
+    }
+
+    // 5xy2/5xy3 transfer VX through VY, in descending order if X > Y,
+    // without touching I.
+    pub(super) fn store_register_range(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        let len = x.max(y) - x.min(y) + 1;
+        self.check_memory(addr, len)?;
+
+        for n in 0..len {
+            let register = if x > y { x - n } else { x + n };
+            self.memory[addr + n] = self.registers[register];
+        }
+        Ok(())
+    }
+
+    pub(super) fn load_register_range(&mut self, x: usize, y: usize) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        let len = x.max(y) - x.min(y) + 1;
+        self.check_memory(addr, len)?;
+
+        for n in 0..len {
+            let register = if x > y { x - n } else { x + n };
+            self.registers[register] = self.memory[addr + n];
+        }
+        Ok(())
+    }
+
+    pub(super) fn store_flags(&mut self, x: usize) {
+        self.flags[..=x].copy_from_slice(&self.registers[..=x]);
+    }
+
+    pub(super) fn load_flags(&mut self, x: usize) {
+        self.registers[..=x].copy_from_slice(&self.flags[..=x]);
+    }
+
+    fn increment_i_after_transfer(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ FONT_START_ADDR, LARGE_GLYPHS, Quirks, XoChip };
+
+    #[test]
+    fn test_load_delay_timer() {
+        let mut chip8 = CPU { delay_timer: 0x2A, ..CPU::default() };
+
+        chip8.load_asm("
+            LD V3, DT
+            EXIT
+        ");
+        assert_eq!(chip8.registers[3], 0);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[3], 0x2A);
+    }
+
+    #[test]
+    fn test_load_key_press_with_key_down() {
+        let mut chip8 = CPU::default();
+
+        chip8.keypad.press(0xB);
+
+        chip8.load_asm("
+            LD V4, K
+            LD V1, 0x01
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[4], 0xB);
+        assert_eq!(chip8.registers[1], 1);
+        assert_eq!(chip8.waiting_for_key, None);
+    }
+
+    #[test]
+    fn test_load_key_press_waits_for_key() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V4, K
+            LD V1, 0x01
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.waiting_for_key, Some(4));
+        assert_eq!(chip8.registers[1], 0);
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn test_set_delay_timer() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[2] = 60;
+
+        chip8.load_asm("
+            LD DT, V2
+            EXIT
+        ");
+        assert_eq!(chip8.delay_timer, 0);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.delay_timer, 60);
+    }
+
+    #[test]
+    fn test_set_sound_timer() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[2] = 30;
+
+        chip8.load_asm("
+            LD ST, V2
+            EXIT
+        ");
+        assert_eq!(chip8.sound_timer, 0);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.sound_timer, 30);
+    }
+
+    #[test]
+    fn test_add_to_i() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[5] = 0x10;
+
+        chip8.load_asm("
+            LD I, 0x300
+            ADD I, V5
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0x310);
+    }
+
+    #[test]
+    fn test_load_sprite_addr() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 0xA;
+
+        chip8.load_asm("
+            LD F, V0
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, (FONT_START_ADDR + 0xA * FONT_SPRITE_SIZE) as u16);
+    }
+
+    #[test]
+    fn test_store_bcd() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[7] = 254;
+
+        chip8.load_asm("
+            LD I, 0x400
+            LD B, V7
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.memory[0x400], 2);
+        assert_eq!(chip8.memory[0x401], 5);
+        assert_eq!(chip8.memory[0x402], 4);
+    }
+
+    #[test]
+    fn test_store_registers() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 1;
+        chip8.registers[1] = 2;
+        chip8.registers[2] = 3;
+        chip8.registers[3] = 4;
+
+        chip8.load_asm("
+            LD I, 0x400
+            LD [I], V2
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.memory[0x400], 1);
+        assert_eq!(chip8.memory[0x401], 2);
+        assert_eq!(chip8.memory[0x402], 3);
+        assert_eq!(chip8.memory[0x403], 0);
+        assert_eq!(chip8.i, 0x400);
+    }
+
+    #[test]
+    fn test_load_registers() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, data
+            LD V2, [I]
+            EXIT
+        data:
+            DB 0x0A, 0x0B, 0x0C, 0x0D
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0x0A);
+        assert_eq!(chip8.registers[1], 0x0B);
+        assert_eq!(chip8.registers[2], 0x0C);
+        assert_eq!(chip8.registers[3], 0);
+        assert_eq!(chip8.i, 0x206);
+    }
+
+    #[test]
+    fn test_store_registers_out_of_bounds() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, 0xffe
+            LD [I], V3
+            EXIT
+        ");
+
+        assert_eq!(chip8.run(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xffe }));
+    }
+
+    #[test]
+    fn test_store_registers_increments_i() {
+        let mut chip8 = CPU::new(Quirks { load_store_increments_i: true, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD I, 0x400
+            LD [I], V2
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0x403);
+    }
+
+    #[test]
+    fn test_load_registers_increments_i() {
+        let mut chip8 = CPU::new(Quirks { load_store_increments_i: true, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD I, 0x400
+            LD V3, [I]
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0x404);
+    }
+
+    #[test]
+    fn test_store_registers_increments_i_at_top_of_memory() {
+        let mut chip8 = CPU::with_variant(&XoChip);
+
+        chip8.load_asm("
+            LD I, LONG 0xFFF0
+            LD [I], VF
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0x0000);
+    }
+
+    #[test]
+    fn test_load_large_sprite_addr() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 0x3;
+
+        chip8.load_asm("
+            LD HF, V0
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i as usize, LARGE_FONT_START_ADDR + 0x3 * 10);
+        assert_eq!(&chip8.memory[chip8.i as usize..chip8.i as usize + 10], &LARGE_GLYPHS[30..40]);
+    }
+
+    #[test]
+    fn test_store_and_load_flags() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 1;
+        chip8.registers[1] = 2;
+        chip8.registers[2] = 3;
+
+        chip8.load_asm("
+            LD R, V1
+            LD V0, 0
+            LD V1, 0
+            LD V2, 0
+            LD V2, R
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 1);
+        assert_eq!(chip8.registers[1], 2);
+        assert_eq!(chip8.registers[2], 0);
+    }
+
+    #[test]
+    fn test_store_register_range() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[2] = 0x22;
+        chip8.registers[3] = 0x33;
+        chip8.registers[4] = 0x44;
+
+        chip8.load_asm("
+            LD I, ascending
+            SAVE V2, V4
+            LD I, descending
+            SAVE V4, V2
+            EXIT
+        ascending:
+            DB 0, 0, 0
+        descending:
+            DB 0, 0, 0
+        ");
+
+        chip8.run().unwrap();
+        let i = chip8.i as usize;
+        assert_eq!(&chip8.memory[i - 3..i], &[0x22, 0x33, 0x44]);
+        assert_eq!(&chip8.memory[i..i + 3], &[0x44, 0x33, 0x22]);
+    }
+
+    #[test]
+    fn test_load_register_range() {
+        let mut chip8 = CPU::new(Quirks { load_store_increments_i: true, ..Quirks::default() });
+
+        chip8.load_asm("
+            LD I, data
+            LOAD V5, V3
+            EXIT
+        data:
+            DB 0x01, 0x02, 0x03
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[5], 0x01);
+        assert_eq!(chip8.registers[4], 0x02);
+        assert_eq!(chip8.registers[3], 0x03);
+        assert_eq!(chip8.memory[chip8.i as usize], 0x01);
+    }
+}
@@ -1,48 +1,154 @@
 use super::CPU;
 
-extern crate rand;
-use rand::{ Rng, SeedableRng };
-use rand::rngs::StdRng;
-
-use std::mem::transmute;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SysBehaviour {
+    Ignore,
+    Trap,
+}
 
 impl CPU {
-    pub(super) fn random(&mut self, x: usize, value: u8) {
-        let seeds: [u8; 32] = unsafe { transmute::<[u64; 4], [u8; 32]>(self.seed) };
-        let mut rng: StdRng = SeedableRng::from_seed(seeds);
-        let random: u8 = rng.gen::<u8>();
+    pub fn set_sys_behaviour(&mut self, behaviour: SysBehaviour) {
+        self.sys_behaviour = behaviour;
+    }
+
+    /// The address of the 0nnn call that stopped execution, if SYS calls are trapped.
+    pub fn sys_call(&self) -> Option<u16> {
+        self.sys_call
+    }
 
-        println!("Random: {}, Value: {}", random, value);
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
 
-        self.registers[x] = random & value;
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub(super) fn sys(&mut self, addr: u16) {
+        match self.sys_behaviour {
+            SysBehaviour::Ignore => {},
+            SysBehaviour::Trap => {
+                self.sys_call = Some(addr);
+                self.halt();
+            },
+        }
+    }
 
-        self.seed = [
-            rng.gen::<u64>(),
-            rng.gen::<u64>(),
-            rng.gen::<u64>(),
-            rng.gen::<u64>(),
-        ];
+    pub(super) fn random(&mut self, x: usize, value: u8) {
+        self.registers[x] = self.random.next_byte() & value;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::{ Chip8Error, ScriptedRandom };
 
     #[test]
     fn test_random() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
-        chip8.set_seed([1, 2, 3, 4]);
+        chip8.set_random_source(Box::new(ScriptedRandom::new(vec![0xFF, 0x5A])));
         chip8.registers[0] = 5;
 
-        test[0] = 0xC0 as u8; test[1] = 0x07 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            RND V0, 0x07
+            RND V1, 0xF0
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
 
-        chip8.run();
-        assert_eq!(chip8.registers[0], 7);
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0x07);
+        assert_eq!(chip8.registers[1], 0x50);
+    }
+
+    #[test]
+    fn test_random_with_seed() {
+        let mut first = CPU::default();
+        let mut second = CPU::default();
+
+        first.set_seed(1234);
+        second.set_seed(1234);
+
+        for chip8 in [&mut first, &mut second].iter_mut() {
+            chip8.load_asm("
+                RND V0, 0xFF
+                RND V1, 0xFF
+                RND V2, 0xFF
+                EXIT
+            ");
+            chip8.run().unwrap();
+        }
+
+        assert_eq!(first.registers, second.registers);
+    }
+
+    #[test]
+    fn test_exit_halts() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 0x01
+            EXIT
+            LD V0, 0x02
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.is_halted());
+        assert_eq!(chip8.registers[0], 1);
+        assert_eq!(chip8.program_counter, 0x204);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 1);
+    }
+
+    #[test]
+    fn test_sys_ignored() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            SYS 0x300
+            SYS 0x000
+            LD V0, 0x02
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 2);
+        assert_eq!(chip8.sys_call(), None);
+    }
+
+    #[test]
+    fn test_sys_trapped() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_sys_behaviour(SysBehaviour::Trap);
+
+        chip8.load_asm("
+            SYS 0x300
+            LD V0, 0x02
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(chip8.is_halted());
+        assert_eq!(chip8.sys_call(), Some(0x300));
+        assert_eq!(chip8.registers[0], 0);
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 0x01
+            DB 0xe0, 0xff
+            EXIT
+        ");
+
+        assert_eq!(chip8.run(), Err(Chip8Error::UnknownOpcode { opcode: 0xE0FF, addr: 0x202 }));
+        assert_eq!(chip8.registers[0], 1);
     }
 }
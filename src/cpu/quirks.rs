@@ -0,0 +1,91 @@
+/// Behaviour of the opcodes that differ between CHIP-8 platforms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift VX in place instead of shifting VY into VX.
+    pub shift_in_place: bool,
+    /// Bnnn jumps to nnn + VX, with X taken from the top nibble of nnn,
+    /// instead of nnn + V0.
+    pub jump_with_vx: bool,
+    /// 8xy1/8xy2/8xy3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Fx55/Fx65 leave I pointing just past the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// Sprite pixels past the edge of the screen are dropped instead of
+    /// wrapping around to the other side.
+    pub clip_sprites: bool,
+}
+
+/// Names accepted by `Quirks::preset`.
+pub const QUIRK_PRESETS: [&str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_in_place: false,
+        jump_with_vx: false,
+        logic_resets_vf: true,
+        load_store_increments_i: true,
+        clip_sprites: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_in_place: true,
+        jump_with_vx: true,
+        logic_resets_vf: false,
+        load_store_increments_i: false,
+        clip_sprites: true,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_in_place: true,
+        jump_with_vx: true,
+        logic_resets_vf: false,
+        load_store_increments_i: false,
+        clip_sprites: true,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_in_place: false,
+        jump_with_vx: false,
+        logic_resets_vf: false,
+        load_store_increments_i: true,
+        clip_sprites: false,
+    };
+
+    /// Looks up a preset by one of the names in `QUIRK_PRESETS`.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" => Some(Quirks::COSMAC_VIP),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip" => Some(Quirks::SUPER_CHIP),
+            "xochip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_in_place: false,
+            jump_with_vx: false,
+            logic_resets_vf: false,
+            load_store_increments_i: false,
+            clip_sprites: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_names() {
+        for name in QUIRK_PRESETS.iter() {
+            assert!(Quirks::preset(name).is_some(), "{}", name);
+        }
+
+        assert_eq!(Quirks::preset("VIP"), Some(Quirks::COSMAC_VIP));
+        assert_eq!(Quirks::preset("megachip"), None);
+    }
+}
@@ -0,0 +1,155 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use std::fmt;
+
+/// Where Cxkk gets its random bytes from.
+pub trait RandomSource: fmt::Debug + Send {
+    fn next_byte(&mut self) -> u8;
+
+    /// The generator state to keep in a save state, if the source can be
+    /// rebuilt from one with `SeededRandom::from_state`. Never all zeroes.
+    fn state(&self) -> Option<[u64; 4]> {
+        None
+    }
+}
+
+/// A deterministic xoshiro256** generator. The sequence for a given seed is
+/// part of the public API and will not change between releases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SeededRandom {
+    state: [u64; 4],
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        // Expand the seed with SplitMix64, as the xoshiro authors recommend.
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+
+        SeededRandom { state: [next(), next(), next(), next()] }
+    }
+
+    /// Resumes from a state returned by `RandomSource::state`. Returns `None`
+    /// for the all-zero state, from which the generator only produces zeroes.
+    pub fn from_state(state: [u64; 4]) -> Option<Self> {
+        if state == [0; 4] {
+            return None;
+        }
+
+        Some(SeededRandom { state })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> Option<[u64; 4]> {
+        Some(self.state)
+    }
+}
+
+/// Bytes straight from the operating system. Used unless a seed is given.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EntropyRandom;
+
+impl RandomSource for EntropyRandom {
+    fn next_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        OsRng.fill_bytes(&mut byte);
+        byte[0]
+    }
+}
+
+/// Plays back a fixed list of bytes, starting again from the beginning once
+/// it runs out. Panics if the list is empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptedRandom {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl ScriptedRandom {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        assert!(!bytes.is_empty(), "ScriptedRandom needs at least one byte");
+
+        ScriptedRandom { bytes, pos: 0 }
+    }
+}
+
+impl RandomSource for ScriptedRandom {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.pos];
+        self.pos = (self.pos + 1) % self.bytes.len();
+        byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequence_is_stable() {
+        let mut random = SeededRandom::new(42);
+        let bytes: Vec<u8> = (0..8).map(|_| random.next_byte()).collect();
+
+        assert_eq!(bytes, vec![21, 97, 174, 236, 253, 197, 184, 217]);
+    }
+
+    #[test]
+    fn test_seeded_state_round_trip() {
+        let mut random = SeededRandom::new(7);
+        random.next_byte();
+
+        let mut resumed = SeededRandom::from_state(random.state().unwrap()).unwrap();
+        for _ in 0..16 {
+            assert_eq!(resumed.next_byte(), random.next_byte());
+        }
+
+        assert_ne!(SeededRandom::new(1), SeededRandom::new(2));
+        assert_eq!(SeededRandom::from_state([0; 4]), None);
+    }
+
+    #[test]
+    fn test_scripted_sequence_repeats() {
+        let mut random = ScriptedRandom::new(vec![1, 2, 3]);
+
+        let bytes: Vec<u8> = (0..5).map(|_| random.next_byte()).collect();
+        assert_eq!(bytes, vec![1, 2, 3, 1, 2]);
+        assert_eq!(random.state(), None);
+    }
+
+    #[test]
+    fn test_entropy_source() {
+        let mut random = EntropyRandom;
+
+        // 64 equal bytes in a row would be a one in 2^504 event.
+        let first = random.next_byte();
+        assert!((0..63).any(|_| random.next_byte() != first));
+        assert_eq!(random.state(), None);
+    }
+}
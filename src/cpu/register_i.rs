@@ -1,27 +1,56 @@
-use super::CPU;
+use super::{ CPU, Chip8Error };
 
 impl CPU {
     pub(super) fn store_register_i(&mut self, addr: u16) {
         self.i = addr;
     }
 
+    // F000 is followed by the full 16-bit address, which is skipped over.
+    pub(super) fn store_register_i_long(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.program_counter;
+        if pc + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { addr: pc });
+        }
+
+        self.i = (self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16;
+        self.advance_counter();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::XO_MEMORY_SIZE;
 
     #[test]
     fn test_store_addr_to_i() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
-
-        test[0] = 0xA2 as u8; test[1] = 0x50 as u8;
+        let mut chip8 = CPU::default();
 
-        chip8.load(test);
+        chip8.load_asm("
+            LD I, 0x250
+            EXIT
+        ");
         assert_eq!(chip8.i, 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.i, 0x250 as u16);
     }
+
+    #[test]
+    fn test_store_long_addr_to_i() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_memory_size(XO_MEMORY_SIZE);
+
+        chip8.load_asm("
+            LD I, LONG 0xBEEF
+            LD V0, 0x01
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.i, 0xBEEF);
+        assert_eq!(chip8.registers[0], 1);
+    }
 }
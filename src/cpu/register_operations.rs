@@ -6,7 +6,7 @@ impl CPU {
     }
 
     pub(super) fn add_register(&mut self, x: usize, value: u8) {
-        self.registers[x] = self.registers[x] + value;
+        self.registers[x] = self.registers[x].wrapping_add(value);
     }
 
     pub(super) fn copy(&mut self, x: usize, y: usize) {
@@ -14,172 +14,206 @@ impl CPU {
     }
 
     pub(super) fn or(&mut self, x: usize, y: usize) {
-        self.registers[x] = self.registers[x] | self.registers[y]
+        self.registers[x] = self.registers[x] | self.registers[y];
+        self.reset_flag_after_logic();
     }
 
     pub(super) fn and(&mut self, x: usize, y: usize) {
-        self.registers[x] = self.registers[x] & self.registers[y]
+        self.registers[x] = self.registers[x] & self.registers[y];
+        self.reset_flag_after_logic();
     }
 
     pub(super) fn xor(&mut self, x: usize, y: usize) {
-        self.registers[x] = self.registers[x] ^ self.registers[y]
+        self.registers[x] = self.registers[x] ^ self.registers[y];
+        self.reset_flag_after_logic();
+    }
+
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[15] = 0;
+        }
     }
 
     pub(super) fn add(&mut self, x: usize, y: usize) {
         let result = (self.registers[x] as u16) + 
             (self.registers[y] as u16);
 
+        // VF is written last so the flag wins when VF is also the destination.
         self.registers[x] = result as u8;
         self.registers[15] = if result > 255 { 1 } else { 0 };
     }
 
     pub(super) fn sub(&mut self, x: usize, y: usize) {
-        self.registers[15] = if self.registers[x] > self.registers[y] { 1 } else { 0 };
-        self.registers[x] = ((self.registers[x] as i16) - 
-            (self.registers[y] as i16)) as u8;
+        let flag = if self.registers[x] >= self.registers[y] { 1 } else { 0 };
+        self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
+        self.registers[15] = flag;
     }
 
     pub(super) fn subn(&mut self, x: usize, y: usize) {
-        self.registers[15] = if self.registers[y] > self.registers[x] { 1 } else { 0 };
-        self.registers[x] = ((self.registers[y] as i16) -
-            (self.registers[x] as i16)) as u8;
+        let flag = if self.registers[y] >= self.registers[x] { 1 } else { 0 };
+        self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
+        self.registers[15] = flag;
     }
 
     pub(super) fn shift_right(&mut self, x: usize, y: usize) {
-        self.registers[15] = self.registers[y] & 1;
-        self.registers[x] = self.registers[y] >> 1;
+        let value = self.registers[self.shift_source(x, y)];
+        self.registers[x] = value >> 1;
+        self.registers[15] = value & 1;
     }
 
     pub(super) fn shift_left(&mut self, x: usize, y: usize) {
-        self.registers[15] = (self.registers[y] & 0b10000000) >> 7;
-        self.registers[x] = self.registers[y] << 1;
+        let value = self.registers[self.shift_source(x, y)];
+        self.registers[x] = value << 1;
+        self.registers[15] = (value & 0b10000000) >> 7;
+    }
+
+    fn shift_source(&self, x: usize, y: usize) -> usize {
+        if self.quirks.shift_in_place { x } else { y }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::Quirks;
 
     #[test]
     fn test_store_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
-
-        test[0] = 0x60 as u8; test[1] = 0x21 as u8;
+        let mut chip8 = CPU::default();
 
-        chip8.load(test);
+        chip8.load_asm("
+            LD V0, 0x21
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0x21 as u8);
     }
 
     #[test]
     fn test_add_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 2;
-        test[0] = 0x70 as u8; test[1] = 0x05 as u8;
 
-        chip8.load(test);
+        chip8.load_asm("
+            ADD V0, 0x05
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 2);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 7);
     }
 
+    #[test]
+    fn test_add_register_wraps() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 0xFE;
+
+        chip8.load_asm("
+            ADD V0, 0x05
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 3);
+        assert_eq!(chip8.registers[15], 0);
+    }
+
     #[test]
     fn test_copy_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[1] = 5;
-        
-        test[0] = 0x80 as u8; test[1] = 0x10 as u8;
 
-        chip8.load(test);
+        chip8.load_asm("
+            LD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0);
         assert_eq!(chip8.registers[1], 5);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 5);
     }
 
     #[test]
     fn test_or_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b01010101 as u8;
         chip8.registers[1] = 0b10101010 as u8;
 
-        test[0] = 0x80 as u8; test[1] = 0x11 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            OR V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b01010101 as u8);
         assert_eq!(chip8.registers[1], 0b10101010 as u8);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b11111111 as u8);
         assert_eq!(chip8.registers[1], 0b10101010 as u8);
     }
 
     #[test]
     fn test_and_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b01010101 as u8;
         chip8.registers[1] = 0b10101010 as u8;
 
-        test[0] = 0x80 as u8; test[1] = 0x12 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            AND V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b01010101 as u8);
         assert_eq!(chip8.registers[1], 0b10101010 as u8);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b00000000 as u8);
         assert_eq!(chip8.registers[1], 0b10101010 as u8);
     }
 
     #[test]
     fn test_xor_register() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b01101001 as u8;
         chip8.registers[1] = 0b11110000 as u8;
 
-        test[0] = 0x80 as u8; test[1] = 0x13 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            XOR V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b01101001 as u8);
         assert_eq!(chip8.registers[1], 0b11110000 as u8);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b10011001 as u8);
         assert_eq!(chip8.registers[1], 0b11110000 as u8);
     }
 
     #[test]
     fn test_add_register_no_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 4;
 
-        test[0] = 0x80 as u8; test[1] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 4);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 9);
         assert_eq!(chip8.registers[1], 4);
         assert_eq!(chip8.registers[15], 0);
@@ -187,20 +221,20 @@ mod tests {
 
     #[test]
     fn test_add_register_with_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 255;
         chip8.registers[1] = 1;
 
-        test[0] = 0x80 as u8; test[1] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 255);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 1);
@@ -208,20 +242,20 @@ mod tests {
 
     #[test]
     fn test_sub_register_no_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 255;
         chip8.registers[1] = 1;
 
-        test[0] = 0x80 as u8; test[1] = 0x15 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SUB V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 255);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 254);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 1);
@@ -229,41 +263,74 @@ mod tests {
 
     #[test]
     fn test_sub_register_with_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0;
         chip8.registers[1] = 1;
 
-        test[0] = 0x80 as u8; test[1] = 0x15 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SUB V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 255);
         assert_eq!(chip8.registers[1], 1);
         assert_eq!(chip8.registers[15], 0);
     }
 
+    #[test]
+    fn test_sub_register_equal_sets_no_borrow() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 7;
+        chip8.registers[1] = 7;
+
+        chip8.load_asm("
+            SUB V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0);
+        assert_eq!(chip8.registers[15], 1);
+    }
+
+    #[test]
+    fn test_sub_into_vf_keeps_flag() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[1] = 3;
+        chip8.registers[15] = 5;
+
+        chip8.load_asm("
+            SUB VF, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 1);
+    }
+
     #[test]
     fn test_shift_right_zero_least_significant_bit() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b10011001 as u8;
         chip8.registers[1] = 0b10110110 as u8;
 
-        test[0] = 0x80 as u8; test[1] = 0x16 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SHR V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b10011001);
         assert_eq!(chip8.registers[1], 0b10110110);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b01011011);
         assert_eq!(chip8.registers[1], 0b10110110);
         assert_eq!(chip8.registers[15], 0);
@@ -271,20 +338,20 @@ mod tests {
 
     #[test]
     fn test_shift_right_one_least_significant_bit() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b10011001 as u8;
         chip8.registers[1] = 0b10110111 as u8;
 
-        test[0] = 0x80 as u8; test[1] = 0x16 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SHR V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b10011001);
         assert_eq!(chip8.registers[1], 0b10110111);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b01011011);
         assert_eq!(chip8.registers[1], 0b10110111);
         assert_eq!(chip8.registers[15], 1);
@@ -292,20 +359,20 @@ mod tests {
 
     #[test]
     fn test_subn_register_no_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 100;
         chip8.registers[1] = 105;
 
-        test[0] = 0x80 as u8; test[1] = 0x17 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SUBN V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 100);
         assert_eq!(chip8.registers[1], 105);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 105);
         assert_eq!(chip8.registers[15], 1);
@@ -313,41 +380,109 @@ mod tests {
 
     #[test]
     fn test_subn_register_with_carry() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 1;
         chip8.registers[1] = 0;
 
-        test[0] = 0x80 as u8; test[1] = 0x17 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SUBN V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 1);
         assert_eq!(chip8.registers[1], 0);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 255);
         assert_eq!(chip8.registers[1], 0);
         assert_eq!(chip8.registers[15], 0);
     }
 
+    #[test]
+    fn test_subn_register_equal_sets_no_borrow() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 7;
+        chip8.registers[1] = 7;
+
+        chip8.load_asm("
+            SUBN V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0);
+        assert_eq!(chip8.registers[15], 1);
+    }
+
+    #[test]
+    fn test_subn_into_vf_keeps_flag() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[1] = 3;
+        chip8.registers[15] = 5;
+
+        chip8.load_asm("
+            SUBN VF, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 0);
+    }
+
+    #[test]
+    fn test_add_into_vf_keeps_flag() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[1] = 0xFF;
+        chip8.registers[15] = 2;
+
+        chip8.load_asm("
+            ADD VF, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 1);
+    }
+
+    #[test]
+    fn test_shift_into_vf_keeps_flag() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[1] = 0b1000_0010;
+        chip8.registers[2] = 0b0000_0101;
+
+        chip8.load_asm("
+            SHL VF, V1
+            LD V3, VF
+            SHR VF, V2
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[3], 1);
+        assert_eq!(chip8.registers[15], 1);
+    }
+
     #[test]
     fn test_shift_left_one_most_significant_bit() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b10011101;
         chip8.registers[1] = 0b11011100;
 
-        test[0] = 0x80 as u8; test[1] = 0x1E as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SHL V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b10011101);
         assert_eq!(chip8.registers[1], 0b11011100);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b10111000);
         assert_eq!(chip8.registers[1], 0b11011100);
         assert_eq!(chip8.registers[15], 1);
@@ -355,22 +490,90 @@ mod tests {
 
     #[test]
     fn test_shift_left_zero_most_significant_bit() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 0b10011101;
         chip8.registers[1] = 0b01011101;
 
-        test[0] = 0x80 as u8; test[1] = 0x1E as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            SHL V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 0b10011101);
         assert_eq!(chip8.registers[1], 0b01011101);
         assert_eq!(chip8.registers[15], 0);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 0b10111010);
         assert_eq!(chip8.registers[1], 0b01011101);
         assert_eq!(chip8.registers[15], 0);
     }
+
+    #[test]
+    fn test_shift_right_in_place() {
+        let mut chip8 = CPU::new(Quirks { shift_in_place: true, ..Quirks::default() });
+
+        chip8.registers[0] = 0b00000101;
+        chip8.registers[1] = 0b11110000;
+
+        chip8.load_asm("
+            SHR V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0b00000010);
+        assert_eq!(chip8.registers[1], 0b11110000);
+        assert_eq!(chip8.registers[15], 1);
+    }
+
+    #[test]
+    fn test_shift_left_in_place() {
+        let mut chip8 = CPU::new(Quirks { shift_in_place: true, ..Quirks::default() });
+
+        chip8.registers[0] = 0b10000001;
+        chip8.registers[1] = 0b00001111;
+
+        chip8.load_asm("
+            SHL V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0b00000010);
+        assert_eq!(chip8.registers[1], 0b00001111);
+        assert_eq!(chip8.registers[15], 1);
+    }
+
+    #[test]
+    fn test_logic_keeps_vf() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[15] = 7;
+
+        chip8.load_asm("
+            OR V0, V1
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[15], 7);
+    }
+
+    #[test]
+    fn test_logic_resets_vf() {
+        for op in ["OR", "AND", "XOR"].iter() {
+            let mut chip8 = CPU::new(Quirks { logic_resets_vf: true, ..Quirks::default() });
+
+            chip8.registers[15] = 7;
+
+            chip8.load_asm(&format!("
+                {} V0, V1
+                EXIT
+            ", op));
+
+            chip8.run().unwrap();
+            assert_eq!(chip8.registers[15], 0, "{}", op);
+        }
+    }
 }
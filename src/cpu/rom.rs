@@ -0,0 +1,173 @@
+use super::{ CPU, MEMORY_SIZE, XO_MEMORY_SIZE, PROGRAM_START_ADDR };
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LoadError {
+    TooLarge { size: usize, max: usize },
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::TooLarge { size, max } =>
+                write!(f, "ROM is {} bytes but only {} bytes fit above {:#05x}", size, max, PROGRAM_START_ADDR),
+            LoadError::Io(err) => write!(f, "Could not read ROM: {}", err),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl CPU {
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Grows or shrinks memory, e.g. to the 64 KiB XO-CHIP address space.
+    /// Panics unless `size` is between `MEMORY_SIZE` and `XO_MEMORY_SIZE`.
+    pub fn set_memory_size(&mut self, size: usize) {
+        assert!((MEMORY_SIZE..=XO_MEMORY_SIZE).contains(&size),
+            "Memory size {} is outside {}..={}", size, MEMORY_SIZE, XO_MEMORY_SIZE);
+
+        self.memory.resize(size, 0);
+    }
+
+    /// Copies `program` to 0x200 and resets the program counter to it.
+    /// Any memory left over from a previous, longer program is zeroed.
+    pub fn load(&mut self, program: &[u8]) -> Result<(), LoadError> {
+        let max = self.memory.len() - PROGRAM_START_ADDR;
+        if program.len() > max {
+            return Err(LoadError::TooLarge { size: program.len(), max });
+        }
+
+        let program_area = &mut self.memory[PROGRAM_START_ADDR..];
+        program_area.iter_mut().for_each(|b| *b = 0);
+        program_area[..program.len()].copy_from_slice(program);
+
+        self.program_counter = PROGRAM_START_ADDR;
+        self.sys_call = None;
+        self.halted = false;
+
+        Ok(())
+    }
+
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoadError> {
+        let program = fs::read(path)?;
+        self.load(&program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ MAX_PROGRAM_SIZE, MAX_XO_PROGRAM_SIZE };
+
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_load_short_program() {
+        let mut chip8 = CPU::default();
+
+        chip8.memory[0x204] = 0xFF;
+
+        chip8.load(&[0x60, 0x21, 0x00, 0xFD]).unwrap();
+        assert_eq!(chip8.memory[0x200], 0x60);
+        assert_eq!(chip8.memory[0x201], 0x21);
+        assert_eq!(chip8.memory[0x204], 0);
+        assert_eq!(chip8.program_counter, 0x200);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[0], 0x21);
+    }
+
+    #[test]
+    fn test_load_full_program() {
+        let mut chip8 = CPU::default();
+
+        let program = [0xAB; MAX_PROGRAM_SIZE];
+
+        chip8.load(&program).unwrap();
+        assert_eq!(chip8.memory[0xFFF], 0xAB);
+    }
+
+    #[test]
+    fn test_load_too_large() {
+        let mut chip8 = CPU::default();
+
+        let program = [0; MAX_PROGRAM_SIZE + 1];
+
+        match chip8.load(&program) {
+            Err(LoadError::TooLarge { size, max }) => {
+                assert_eq!(size, 3585);
+                assert_eq!(max, 3584);
+            },
+            other => panic!("Expected TooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_into_xo_memory() {
+        let mut chip8 = CPU::default();
+
+        assert_eq!(chip8.memory_size(), MEMORY_SIZE);
+        chip8.set_memory_size(XO_MEMORY_SIZE);
+        assert_eq!(chip8.memory_size(), XO_MEMORY_SIZE);
+
+        let program = vec![0xAB; MAX_XO_PROGRAM_SIZE];
+
+        chip8.load(&program).unwrap();
+        assert_eq!(chip8.memory[0xFFFF], 0xAB);
+        assert!(chip8.load(&[0; MAX_XO_PROGRAM_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_memory_size_too_small() {
+        CPU::default().set_memory_size(0x800);
+    }
+
+    #[test]
+    fn test_load_file() {
+        let mut chip8 = CPU::default();
+
+        let path = env::temp_dir().join(format!("chip8-load-{}.ch8", process::id()));
+
+        fs::write(&path, [0x61, 0x07, 0x00, 0xFD]).unwrap();
+        let result = chip8.load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+        chip8.run().unwrap();
+        assert_eq!(chip8.registers[1], 0x07);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let mut chip8 = CPU::default();
+
+        match chip8.load_file("/nonexistent/rom.ch8") {
+            Err(LoadError::Io(_)) => {},
+            other => panic!("Expected Io, got {:?}", other),
+        }
+    }
+}
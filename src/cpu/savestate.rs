@@ -0,0 +1,489 @@
+use super::{ CPU, Quirks, SeededRandom, PLANE_COUNT, HIRES_DISPLAY_HEIGHT, AUDIO_PATTERN_SIZE,
+    MEMORY_SIZE, XO_MEMORY_SIZE, FONT_SIZE, PROGRAM_START_ADDR };
+
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// Bumped whenever the layout of a save state changes.
+pub const STATE_VERSION: u16 = 2;
+
+const CHECKSUM_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion { version: u16 },
+    /// The state was saved from a CPU for another variant. An empty name
+    /// means a CPU created without one.
+    VariantMismatch { expected: String, found: String },
+    ChecksumMismatch,
+    Truncated,
+    Invalid { field: &'static str },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |name: &str| if name.is_empty() { "no variant".to_string() } else { name.to_string() };
+
+        match self {
+            StateError::NotAState => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion { version } =>
+                write!(f, "Save state version {} is not supported (expected {})", version, STATE_VERSION),
+            StateError::VariantMismatch { expected, found } =>
+                write!(f, "Save state is for {} but this machine is {}", describe(found), describe(expected)),
+            StateError::ChecksumMismatch => write!(f, "Save state is corrupt (checksum mismatch)"),
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Invalid { field } => write!(f, "Save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(StateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid { field }),
+        }
+    }
+}
+
+// Option<usize> and Option<u16> are stored as 0xFFFF_FFFF for None.
+const NONE: u32 = u32::MAX;
+
+impl CPU {
+    /// Serialises the whole machine. The result starts with a magic number,
+    /// format version and variant name, and ends with a CRC-32 of the rest.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Writer { data: Vec::new() };
+
+        let variant = self.variant.map_or("", |variant| variant.name());
+        out.bytes(MAGIC);
+        out.u16(STATE_VERSION);
+        out.u8(variant.len() as u8);
+        out.bytes(variant.as_bytes());
+
+        out.bytes(&self.registers);
+        out.u32(self.program_counter as u32);
+        self.stack.iter().for_each(|&addr| out.u16(addr));
+        out.u8(self.stack_pointer as u8);
+        out.u16(self.i);
+        let random = self.random.state();
+        out.bool(random.is_some());
+        random.unwrap_or_default().iter().for_each(|&word| out.u64(word));
+        out.u8(self.delay_timer);
+        out.u8(self.sound_timer);
+        out.u32(self.instructions_per_second);
+        out.u32(self.timer_cycles);
+        out.u16((0..16).filter(|&key| self.keypad.is_pressed(key)).fold(0, |mask, key| mask | 1 << key));
+        out.u32(self.waiting_for_key.map_or(NONE, |x| x as u32));
+        out.u32(self.sys_call.map_or(NONE, u32::from));
+        out.bool(self.halted);
+
+        let quirks = self.quirks;
+        for &quirk in [quirks.shift_in_place, quirks.jump_with_vx, quirks.logic_resets_vf,
+            quirks.load_store_increments_i, quirks.clip_sprites].iter() {
+            out.bool(quirk);
+        }
+
+        out.u32(self.font_addr as u32);
+        out.bytes(&self.flags);
+        out.bytes(&self.audio_pattern);
+        out.bool(self.has_audio_pattern);
+        out.u8(self.pitch);
+
+        out.bool(self.hires);
+        out.u8(self.selected_planes);
+        for plane in self.display.iter() {
+            for column in plane.iter() {
+                for pixels in column.chunks(8) {
+                    out.u8(pixels.iter().fold(0, |byte, &lit| byte << 1 | lit as u8));
+                }
+            }
+        }
+
+        out.u32(self.memory.len() as u32);
+        out.bytes(&self.memory);
+
+        let checksum = crc32fast::hash(&out.data);
+        out.u32(checksum);
+        out.data
+    }
+
+    /// Restores a machine saved by `save_state`. The CPU is left untouched if
+    /// the state is damaged, from another format version or for another variant.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() < MAGIC.len() + 2 || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotAState);
+        }
+
+        let mut header = Reader { data, pos: MAGIC.len() };
+        let version = header.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+
+        if data.len() < CHECKSUM_SIZE {
+            return Err(StateError::Truncated);
+        }
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if crc32fast::hash(body) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        let mut state = Reader { data: body, pos: header.pos };
+        let name_len = state.u8()? as usize;
+        let found = String::from_utf8_lossy(state.bytes(name_len)?).into_owned();
+        let expected = self.variant.map_or("", |variant| variant.name());
+        if found != expected {
+            return Err(StateError::VariantMismatch { expected: expected.to_string(), found });
+        }
+
+        // Everything is read into a copy first so a bad state changes nothing.
+        let mut cpu = CPU::new(self.quirks);
+        cpu.variant = self.variant;
+        cpu.sys_behaviour = self.sys_behaviour;
+
+        cpu.registers = state.array()?;
+        cpu.program_counter = state.u32()? as usize;
+        for addr in cpu.stack.iter_mut() {
+            *addr = state.u16()?;
+        }
+        cpu.stack_pointer = state.u8()? as usize;
+        if cpu.stack_pointer > cpu.stack.len() {
+            return Err(StateError::Invalid { field: "stack pointer" });
+        }
+        cpu.i = state.u16()?;
+        let seeded = state.bool("random source flag")?;
+        let random = [state.u64()?, state.u64()?, state.u64()?, state.u64()?];
+        cpu.delay_timer = state.u8()?;
+        cpu.sound_timer = state.u8()?;
+        cpu.instructions_per_second = state.u32()?.max(1);
+        cpu.timer_cycles = state.u32()?;
+        if cpu.timer_cycles >= cpu.instructions_per_second {
+            return Err(StateError::Invalid { field: "timer cycles" });
+        }
+        let keys = state.u16()?;
+        (0..16).filter(|key| keys & (1 << key) != 0).for_each(|key| cpu.keypad.press(key));
+        cpu.waiting_for_key = match state.u32()? {
+            NONE => None,
+            x if x < 16 => Some(x as usize),
+            _ => return Err(StateError::Invalid { field: "key register" }),
+        };
+        cpu.sys_call = match state.u32()? {
+            NONE => None,
+            addr => Some(addr as u16),
+        };
+        cpu.halted = state.bool("halted flag")?;
+
+        cpu.quirks = Quirks {
+            shift_in_place: state.bool("quirk")?,
+            jump_with_vx: state.bool("quirk")?,
+            logic_resets_vf: state.bool("quirk")?,
+            load_store_increments_i: state.bool("quirk")?,
+            clip_sprites: state.bool("quirk")?,
+        };
+
+        cpu.font_addr = state.u32()? as usize;
+        if cpu.font_addr > PROGRAM_START_ADDR - FONT_SIZE {
+            return Err(StateError::Invalid { field: "font address" });
+        }
+        cpu.flags = state.array()?;
+        cpu.audio_pattern = state.array::<AUDIO_PATTERN_SIZE>()?;
+        cpu.has_audio_pattern = state.bool("audio pattern flag")?;
+        cpu.pitch = state.u8()?;
+
+        cpu.hires = state.bool("resolution")?;
+        cpu.selected_planes = state.u8()?;
+        if cpu.selected_planes >= 1 << PLANE_COUNT {
+            return Err(StateError::Invalid { field: "plane selection" });
+        }
+        for plane in cpu.display.iter_mut() {
+            for column in plane.iter_mut() {
+                for (chunk, &byte) in column.chunks_mut(8).zip(state.bytes(HIRES_DISPLAY_HEIGHT / 8)?) {
+                    for (bit, pixel) in chunk.iter_mut().enumerate() {
+                        *pixel = byte & (0x80 >> bit) != 0;
+                    }
+                }
+            }
+        }
+
+        let memory_size = state.u32()? as usize;
+        let expected_size = self.variant.map(|variant| variant.memory_size());
+        if !(MEMORY_SIZE..=XO_MEMORY_SIZE).contains(&memory_size) || expected_size.is_some_and(|size| size != memory_size) {
+            return Err(StateError::Invalid { field: "memory size" });
+        }
+        cpu.memory = state.bytes(memory_size)?.to_vec();
+
+        if state.pos != body.len() {
+            return Err(StateError::Invalid { field: "length" });
+        }
+
+        // A source with no state to save, such as the OS one, is kept as is.
+        if seeded {
+            let random = SeededRandom::from_state(random).ok_or(StateError::Invalid { field: "random state" })?;
+            cpu.random = Box::new(random);
+        } else {
+            std::mem::swap(&mut cpu.random, &mut self.random);
+        }
+
+        cpu.mark_all_dirty();
+        *self = cpu;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ ScriptedRandom, SuperChip, XoChip };
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_seed(1234);
+        chip8.press_key(0xB);
+
+        chip8.load_asm("
+            LD V0, 0x12
+            LD V1, 0x34
+            LD I, sprite
+            DRW V0, V1, 2
+            CALL routine
+            LD V2, 0x56
+            EXIT
+        routine:
+            LD ST, V0
+            LD DT, V1
+            LD R, V1
+            RET
+        sprite:
+            DB 0xAA, 0x55
+        ");
+
+        chip8.run_cycles(6).unwrap();
+
+        let state = chip8.save_state();
+        let mut restored = CPU::default();
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(restored.registers, chip8.registers);
+        assert_eq!(restored.program_counter, chip8.program_counter);
+        assert_eq!(restored.stack, chip8.stack);
+        assert_eq!(restored.stack_pointer, 1);
+        assert_eq!(restored.i, chip8.i);
+        assert_eq!(restored.random.state(), chip8.random.state());
+        assert_eq!(restored.sound_timer, chip8.sound_timer);
+        assert_eq!(restored.delay_timer, chip8.delay_timer);
+        assert_eq!(restored.flags, chip8.flags);
+        assert!(restored.keypad().is_pressed(0xB));
+        assert_eq!(restored.memory, chip8.memory);
+        assert_eq!(restored.render_text(), chip8.render_text());
+        assert!(restored.is_dirty());
+        assert_eq!(restored.save_state(), state);
+
+        restored.run().unwrap();
+        assert_eq!(restored.registers[2], 0x56);
+    }
+
+    #[test]
+    fn test_restore_xo_chip_state() {
+        let mut chip8 = CPU::with_variant(&XoChip);
+
+        chip8.load_asm("
+            HIGH
+            PLANE 2
+            LD I, LONG 0x1000
+            LD V0, 0x40
+            PITCH V0
+            EXIT
+        ");
+        chip8.memory[0xFFFF] = 0x99;
+        chip8.run().unwrap();
+
+        let mut restored = CPU::with_variant(&XoChip);
+        restored.load_state(&chip8.save_state()).unwrap();
+
+        assert!(restored.is_hires());
+        assert_eq!(restored.selected_planes(), 2);
+        assert_eq!(restored.i, 0x1000);
+        assert_eq!(restored.pitch(), 0x40);
+        assert_eq!(restored.memory_size(), 0x10000);
+        assert_eq!(restored.memory[0xFFFF], 0x99);
+        assert_eq!(restored.quirks(), Quirks::XO_CHIP);
+    }
+
+    #[test]
+    fn test_variant_mismatch() {
+        let state = CPU::with_variant(&SuperChip).save_state();
+
+        let mut chip8 = CPU::with_variant(&XoChip);
+        let err = chip8.load_state(&state).unwrap_err();
+        assert_eq!(err, StateError::VariantMismatch { expected: "xochip".to_string(), found: "schip".to_string() });
+        assert_eq!(err.to_string(), "Save state is for schip but this machine is xochip");
+
+        assert!(CPU::default().load_state(&state).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut state = CPU::default().save_state();
+        state[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+
+        assert_eq!(CPU::default().load_state(&state), Err(StateError::UnsupportedVersion { version: STATE_VERSION + 1 }));
+    }
+
+    #[test]
+    fn test_invalid_timer_cycles() {
+        let mut chip8 = CPU::new(Quirks::default());
+
+        chip8.timer_cycles = u32::MAX;
+
+        let mut target = CPU::default();
+        assert_eq!(target.load_state(&chip8.save_state()), Err(StateError::Invalid { field: "timer cycles" }));
+        assert_eq!(target.timer_cycles, 0);
+    }
+
+    #[test]
+    fn test_font_must_end_below_program() {
+        let mut chip8 = CPU::new(Quirks::default());
+
+        chip8.font_addr = PROGRAM_START_ADDR - FONT_SIZE + 1;
+
+        let mut target = CPU::default();
+        assert_eq!(target.load_state(&chip8.save_state()), Err(StateError::Invalid { field: "font address" }));
+        assert_eq!(target.font_addr, CPU::default().font_addr);
+
+        chip8.font_addr = PROGRAM_START_ADDR - FONT_SIZE;
+        assert!(target.load_state(&chip8.save_state()).is_ok());
+    }
+
+    #[test]
+    fn test_memory_size_must_match_variant() {
+        let mut chip8 = CPU::with_variant(&XoChip);
+
+        chip8.set_memory_size(MEMORY_SIZE);
+
+        let mut target = CPU::with_variant(&XoChip);
+        assert_eq!(target.load_state(&chip8.save_state()), Err(StateError::Invalid { field: "memory size" }));
+        assert_eq!(target.memory_size(), XO_MEMORY_SIZE);
+
+        // Without a variant any supported size is accepted.
+        let mut chip8 = CPU::default();
+        chip8.set_memory_size(XO_MEMORY_SIZE);
+        assert!(CPU::default().load_state(&chip8.save_state()).is_ok());
+    }
+
+    #[test]
+    fn test_zero_random_state() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_seed(1);
+
+        let mut target = CPU::default();
+        target.set_random_source(Box::new(ScriptedRandom::new(vec![7])));
+
+        // Overwrite the generator state in the body and fix up the checksum.
+        let words: Vec<u8> = chip8.random.state().unwrap().iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut state = chip8.save_state();
+        let pos = state.windows(words.len()).position(|window| window == &words[..]).unwrap();
+        state[pos..pos + words.len()].iter_mut().for_each(|byte| *byte = 0);
+        let body = state.len() - CHECKSUM_SIZE;
+        let checksum = crc32fast::hash(&state[..body]).to_le_bytes();
+        state[body..].copy_from_slice(&checksum);
+
+        assert_eq!(target.load_state(&state), Err(StateError::Invalid { field: "random state" }));
+        assert_eq!(target.random.next_byte(), 7);
+    }
+
+    #[test]
+    fn test_corrupt_state_is_rejected() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 0x12
+            DRW V0, V0, 1
+            EXIT
+        ");
+
+        let mut state = chip8.save_state();
+        let len = state.len();
+        state[len / 2] ^= 0xFF;
+
+        let mut target = CPU::default();
+        target.registers[0] = 0x77;
+        assert_eq!(target.load_state(&state), Err(StateError::ChecksumMismatch));
+        assert_eq!(target.registers[0], 0x77);
+
+        assert_eq!(target.load_state(b"PNG!"), Err(StateError::NotAState));
+        assert_eq!(target.load_state(&chip8.save_state()[..6]), Err(StateError::ChecksumMismatch));
+    }
+}
@@ -0,0 +1,166 @@
+use super::{ CPU, Chip8Error };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepResult {
+    Executed { addr: usize, opcode: u16 },
+    WaitingForKey,
+    Halted,
+}
+
+impl StepResult {
+    pub fn is_executed(&self) -> bool {
+        matches!(self, StepResult::Executed { .. })
+    }
+}
+
+impl CPU {
+    /// Runs until the program halts or waits for a key.
+    pub fn run(&mut self) -> Result<(), Chip8Error> {
+        while self.step()?.is_executed() {}
+        Ok(())
+    }
+
+    /// Runs at most `cycles` instructions, stopping early if the program halts
+    /// or waits for a key. Returns how many ran.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, Chip8Error> {
+        self.run_while(|_, executed| executed < cycles)
+    }
+
+    /// Runs until `predicate` holds for the machine, checked before every
+    /// instruction, or until the program halts or waits for a key.
+    /// Returns how many instructions ran.
+    pub fn run_until<F: FnMut(&CPU) -> bool>(&mut self, mut predicate: F) -> Result<u64, Chip8Error> {
+        self.run_while(|cpu, _| !predicate(cpu))
+    }
+
+    fn run_while<F: FnMut(&CPU, u64) -> bool>(&mut self, mut condition: F) -> Result<u64, Chip8Error> {
+        let mut executed = 0;
+        while condition(self, executed) && self.step()?.is_executed() {
+            executed += 1;
+        }
+
+        Ok(executed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_executes_one_instruction() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 0x01
+            LD V1, 0x02
+            EXIT
+        ");
+
+        assert_eq!(chip8.step(), Ok(StepResult::Executed { addr: 0x200, opcode: 0x6001 }));
+        assert_eq!(chip8.registers[0], 1);
+        assert_eq!(chip8.registers[1], 0);
+        assert_eq!(chip8.program_counter, 0x202);
+
+        assert_eq!(chip8.step(), Ok(StepResult::Executed { addr: 0x202, opcode: 0x6102 }));
+        assert_eq!(chip8.registers[1], 2);
+    }
+
+    #[test]
+    fn test_step_reports_halt() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            EXIT
+        ");
+
+        assert_eq!(chip8.step(), Ok(StepResult::Executed { addr: 0x200, opcode: 0x00FD }));
+        assert_eq!(chip8.step(), Ok(StepResult::Halted));
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn test_step_reports_waiting_for_key() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, K
+            EXIT
+        ");
+
+        assert!(chip8.step().unwrap().is_executed());
+        assert_eq!(chip8.step(), Ok(StepResult::WaitingForKey));
+
+        chip8.press_key(0x1);
+        assert!(chip8.step().unwrap().is_executed());
+    }
+
+    #[test]
+    fn test_step_reports_error() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            RET
+            EXIT
+        ");
+
+        assert_eq!(chip8.step(), Err(Chip8Error::StackUnderflow { addr: 0x200 }));
+    }
+
+    #[test]
+    fn test_run_cycles_stops_after_count() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 0x01
+            JP loop
+        ");
+
+        assert_eq!(chip8.run_cycles(10).unwrap(), 10);
+        assert_eq!(chip8.registers[0], 5);
+        assert_eq!(chip8.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_run_cycles_stops_on_halt() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            ADD V0, 0x01
+            EXIT
+        ");
+
+        assert_eq!(chip8.run_cycles(10).unwrap(), 2);
+        assert!(chip8.is_halted());
+        assert_eq!(chip8.run_cycles(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 0x01
+            JP loop
+        ");
+
+        assert_eq!(chip8.run_until(|cpu| cpu.registers[0] == 3).unwrap(), 5);
+        assert_eq!(chip8.registers[0], 3);
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn test_run_until_stops_on_halt() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            ADD V0, 0x01
+            EXIT
+        ");
+
+        assert_eq!(chip8.run_until(|_| false).unwrap(), 2);
+        assert!(chip8.is_halted());
+    }
+}
@@ -1,24 +1,28 @@
-use super::CPU;
+use super::{ CPU, Chip8Error };
 
 impl CPU {
-    pub(super) fn call(&mut self, addr: u16) {
+    pub(super) fn call(&mut self, addr: u16) -> Result<(), Chip8Error> {
         if self.stack_pointer >= self.stack.len() {
-            panic!("No more space on the stack");
+            return Err(Chip8Error::StackOverflow { addr: self.program_counter - 2 });
         }
 
         self.stack[self.stack_pointer] = self.program_counter as u16;
         self.stack_pointer += 1;
         self.program_counter = addr as usize;
+
+        Ok(())
     }
 
-    pub(super) fn ret(&mut self) {
-        if self.stack_pointer <= 0 {
-            panic!("No more subroutine to return to");
+    pub(super) fn ret(&mut self) -> Result<(), Chip8Error> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8Error::StackUnderflow { addr: self.program_counter - 2 });
         }
 
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer] as usize;
         self.stack[self.stack_pointer] = 0;
+
+        Ok(())
     }
 
     pub(super) fn jump(&mut self, addr: u16) {
@@ -26,7 +30,8 @@ impl CPU {
     }
 
     pub(super) fn jump_add_v0(&mut self, addr: u16) {
-        let value = self.registers[0] as u16;
+        let register = if self.quirks.jump_with_vx { (addr >> 8) as usize } else { 0 };
+        let value = self.registers[register] as u16;
         self.program_counter = (addr + value) as usize;
     }
 }
@@ -34,88 +39,116 @@ impl CPU {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::Quirks;
 
     #[test]
     fn test_subroutine_and_return() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 6;
 
-        test[0] = 0x22 as u8; test[1] = 0x0C as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[12] = 0x80 as u8; test[13] = 0x14 as u8;
-        test[14] = 0x00 as u8; test[15] = 0xEE as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            CALL add
+            ADD V0, V1
+            EXIT
+        add:
+            ADD V0, V1
+            RET
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 6);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 17);
         assert_eq!(chip8.registers[1], 6);
     }
 
     #[test]
-    #[should_panic]
     fn test_return_with_no_stack() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
-        test[0] = 0x00 as u8; test[1] = 0xEE as u8;
-
-        chip8.load(test);
-        chip8.run();
+        chip8.load_asm("
+            RET
+            EXIT
+        ");
+        assert_eq!(chip8.run(), Err(Chip8Error::StackUnderflow { addr: 0x200 }));
     }
 
     #[test]
-    #[should_panic]
     fn test_subroutine_overflow() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
-
-        test[0] = 0x22 as u8; test[1] = 0x00 as u8;
+        let mut chip8 = CPU::default();
 
-        chip8.load(test);
-        chip8.run();
+        chip8.load_asm("
+        start:
+            CALL start
+        ");
+        assert_eq!(chip8.run(), Err(Chip8Error::StackOverflow { addr: 0x200 }));
     }
 
     #[test]
     fn test_jump() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 5;
         chip8.registers[1] = 6;
 
-        test[0] = 0x12 as u8; test[1] = 0x0C as u8;
-        test[2] = 0x80 as u8; test[3] = 0x14 as u8;
-        test[12] = 0x80 as u8; test[13] = 0x14 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            JP skip
+            ADD V0, V1
+        skip:
+            ADD V0, V1
+            EXIT
+        ");
         assert_eq!(chip8.registers[0], 5);
         assert_eq!(chip8.registers[1], 6);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.registers[0], 11);
         assert_eq!(chip8.registers[1], 6);
     }
 
     #[test]
     fn test_register_jump() {
-        let mut chip8 = CPU::new();
-        let mut test = chip8.blank_program();
+        let mut chip8 = CPU::default();
 
         chip8.registers[0] = 2 as u8;
 
-        test[0] = 0xB2 as u8; test[1] = 0x00 as u8;
-
-        chip8.load(test);
+        chip8.load_asm("
+            JP V0, 0x200
+            EXIT
+        ");
         assert_eq!(chip8.program_counter, 0x200);
 
-        chip8.run();
+        chip8.run().unwrap();
         assert_eq!(chip8.program_counter, 0x204); // Reason for 0x204 because the program counter increases one more time to end the program
     }
-}
 
+    #[test]
+    fn test_jump_out_of_memory() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            JP 0xfff
+            EXIT
+        ");
+
+        assert_eq!(chip8.run(), Err(Chip8Error::PcOutOfBounds { addr: 0xFFF }));
+    }
+
+    #[test]
+    fn test_register_jump_with_vx() {
+        let mut chip8 = CPU::new(Quirks { jump_with_vx: true, ..Quirks::default() });
+
+        chip8.registers[0] = 8;
+        chip8.registers[2] = 4;
+
+        chip8.load_asm("
+            DW 0xB200       ; JP V2, 0x200 with this quirk
+            EXIT
+        ");
+
+        chip8.step().unwrap();
+        assert_eq!(chip8.program_counter, 0x204);
+    }
+}
@@ -0,0 +1,192 @@
+use super::{ CPU, Chip8Error, TIMER_FREQUENCY };
+
+impl CPU {
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second.max(1);
+        self.timer_cycles = 0;
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Whether the buzzer sounded during the frame that the last timer tick
+    /// ended. A sound timer set to N keeps it on for N frames.
+    pub fn buzzer_sounded(&self) -> bool {
+        self.buzzed
+    }
+
+    /// Counts both timers down by one, as happens sixty times a second.
+    pub fn tick_timers(&mut self) {
+        self.buzzed = self.sound_timer > 0;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// How many instructions the next `run_frame` runs, unless the program
+    /// stops first.
+    pub fn frame_cycles(&self) -> u64 {
+        u64::from((self.instructions_per_second - self.timer_cycles).div_ceil(TIMER_FREQUENCY))
+    }
+
+    /// Runs one 60 Hz frame: the instructions that fit into it at the current
+    /// rate, then the timer tick that ends it. The tick happens even if the
+    /// program halts or waits for a key, so the timers keep counting down
+    /// while nothing executes. Below 60 instructions per second a frame is
+    /// a single instruction and may span several ticks.
+    /// Returns how many instructions ran.
+    pub fn run_frame(&mut self) -> Result<u64, Chip8Error> {
+        let cycles = self.frame_cycles();
+        let executed = self.run_cycles(cycles)?;
+
+        // The last instruction would have ticked the timers; do it for them.
+        if executed < cycles {
+            self.tick_timers();
+            self.timer_cycles = 0;
+        }
+
+        Ok(executed)
+    }
+
+    // Called once per executed instruction; spreads the 60 Hz ticks evenly
+    // across however many instructions make up a second.
+    pub(super) fn cycle_timers(&mut self) {
+        self.timer_cycles += TIMER_FREQUENCY;
+
+        while self.timer_cycles >= self.instructions_per_second {
+            self.timer_cycles -= self.instructions_per_second;
+            self.tick_timers();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_tick_every_instruction_at_60_ips() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_instructions_per_second(60);
+        chip8.registers[0] = 10;
+
+        chip8.load_asm("
+            LD DT, V0
+            LD ST, V0
+            LD V1, 0x01
+            LD V1, 0x02
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.delay_timer(), 5);
+        assert_eq!(chip8.sound_timer(), 6);
+        assert!(chip8.sound_active());
+    }
+
+    #[test]
+    fn test_timers_tick_at_60hz_independent_of_rate() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_instructions_per_second(600);
+        chip8.registers[0] = 10;
+
+        let mut source = String::from("LD DT, V0\n");
+        source.push_str(&"LD V1, 0x01\n".repeat(19));
+        source.push_str("EXIT\n");
+
+        chip8.load_asm(&source);
+
+        chip8.run().unwrap();
+        assert_eq!(chip8.delay_timer(), 8);
+    }
+
+    #[test]
+    fn test_run_frame_ticks_once() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_instructions_per_second(600);
+        chip8.registers[0] = 10;
+
+        chip8.load_asm("
+            LD DT, V0
+        loop:
+            ADD V1, 0x01
+            JP loop
+        ");
+
+        assert_eq!(chip8.frame_cycles(), 10);
+        assert_eq!(chip8.run_frame().unwrap(), 10);
+        assert_eq!(chip8.delay_timer(), 9);
+
+        assert_eq!(chip8.run_frame().unwrap(), 10);
+        assert_eq!(chip8.delay_timer(), 8);
+    }
+
+    #[test]
+    fn test_run_frame_ticks_while_waiting_for_key() {
+        let mut chip8 = CPU::default();
+
+        chip8.set_instructions_per_second(600);
+        chip8.registers[0] = 10;
+
+        chip8.load_asm("
+            LD DT, V0
+            LD V1, K
+            EXIT
+        ");
+
+        assert_eq!(chip8.run_frame().unwrap(), 2);
+        assert_eq!(chip8.delay_timer(), 9);
+
+        assert_eq!(chip8.run_frame().unwrap(), 0);
+        assert_eq!(chip8.run_frame().unwrap(), 0);
+        assert_eq!(chip8.delay_timer(), 7);
+        assert!(chip8.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_run_frame_ticks_after_halt() {
+        let mut chip8 = CPU::default();
+
+        chip8.registers[0] = 3;
+
+        chip8.load_asm("
+            LD ST, V0
+            EXIT
+        ");
+
+        assert_eq!(chip8.run_frame().unwrap(), 2);
+        assert_eq!(chip8.run_frame().unwrap(), 0);
+        assert_eq!(chip8.run_frame().unwrap(), 0);
+        assert_eq!(chip8.sound_timer(), 0);
+        assert!(!chip8.sound_active());
+    }
+
+    #[test]
+    fn test_timers_stop_at_zero() {
+        let mut chip8 = CPU { delay_timer: 1, sound_timer: 1, ..CPU::default() };
+
+        chip8.tick_timers();
+        assert_eq!(chip8.delay_timer(), 0);
+        assert_eq!(chip8.sound_timer(), 0);
+        assert!(!chip8.sound_active());
+
+        chip8.tick_timers();
+        assert_eq!(chip8.delay_timer(), 0);
+        assert_eq!(chip8.sound_timer(), 0);
+    }
+}
@@ -0,0 +1,252 @@
+use super::{ Instruction, Quirks, MEMORY_SIZE, XO_MEMORY_SIZE, DISPLAY_WIDTH, DISPLAY_HEIGHT,
+    HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, MAX_PROGRAM_SIZE };
+use crate::disassembler::{ self, Line };
+
+use std::fmt;
+
+/// Groups of opcodes, each a superset of the one before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstructionSet {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+/// A CHIP-8 platform: how much memory it has, how large its display can get,
+/// which opcodes it understands and how the ambiguous ones behave.
+pub trait Variant: fmt::Debug + Sync {
+    /// Short name, as accepted by `variant`.
+    fn name(&self) -> &'static str;
+
+    fn memory_size(&self) -> usize;
+
+    /// Width and height of the display at its highest resolution.
+    fn display_size(&self) -> (usize, usize);
+
+    fn quirks(&self) -> Quirks;
+
+    fn supports(&self, instruction: &Instruction) -> bool;
+}
+
+impl PartialEq for dyn Variant {
+    fn eq(&self, other: &dyn Variant) -> bool {
+        self.name() == other.name()
+    }
+}
+
+/// The original COSMAC VIP interpreter.
+#[derive(Clone, Copy, Debug)]
+pub struct Chip8;
+
+/// The VIP interpreter for the colour board, which reuses Bnnn to set the
+/// foreground colour. Its colour and I/O opcodes are not decoded, so ROMs
+/// that use them stop with an unknown opcode.
+#[derive(Clone, Copy, Debug)]
+pub struct Chip8X;
+
+/// The HP-48 port, which changed the shift, jump and load/store behaviour.
+#[derive(Clone, Copy, Debug)]
+pub struct Chip48;
+
+/// SUPER-CHIP 1.1, adding the 128x64 mode, scrolling and large font.
+#[derive(Clone, Copy, Debug)]
+pub struct SuperChip;
+
+/// XO-CHIP, adding 64 KiB of memory, a second plane and audio patterns.
+#[derive(Clone, Copy, Debug)]
+pub struct XoChip;
+
+impl Variant for Chip8 {
+    fn name(&self) -> &'static str { "chip8" }
+    fn memory_size(&self) -> usize { MEMORY_SIZE }
+    fn display_size(&self) -> (usize, usize) { (DISPLAY_WIDTH, DISPLAY_HEIGHT) }
+    fn quirks(&self) -> Quirks { Quirks::COSMAC_VIP }
+
+    fn supports(&self, instruction: &Instruction) -> bool {
+        match instruction.instruction_set() {
+            InstructionSet::Chip8 => true,
+            InstructionSet::SuperChip | InstructionSet::XoChip => false,
+        }
+    }
+}
+
+impl Variant for Chip8X {
+    fn name(&self) -> &'static str { "chip8x" }
+    fn memory_size(&self) -> usize { MEMORY_SIZE }
+    fn display_size(&self) -> (usize, usize) { (DISPLAY_WIDTH, DISPLAY_HEIGHT) }
+    fn quirks(&self) -> Quirks { Quirks::COSMAC_VIP }
+
+    fn supports(&self, instruction: &Instruction) -> bool {
+        match *instruction {
+            Instruction::JumpV0 { .. } => false,
+            _ => Chip8.supports(instruction),
+        }
+    }
+}
+
+impl Variant for Chip48 {
+    fn name(&self) -> &'static str { "chip48" }
+    fn memory_size(&self) -> usize { MEMORY_SIZE }
+    fn display_size(&self) -> (usize, usize) { (DISPLAY_WIDTH, DISPLAY_HEIGHT) }
+    fn quirks(&self) -> Quirks { Quirks::CHIP_48 }
+
+    fn supports(&self, instruction: &Instruction) -> bool {
+        Chip8.supports(instruction)
+    }
+}
+
+impl Variant for SuperChip {
+    fn name(&self) -> &'static str { "schip" }
+    fn memory_size(&self) -> usize { MEMORY_SIZE }
+    fn display_size(&self) -> (usize, usize) { (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT) }
+    fn quirks(&self) -> Quirks { Quirks::SUPER_CHIP }
+
+    fn supports(&self, instruction: &Instruction) -> bool {
+        match instruction.instruction_set() {
+            InstructionSet::Chip8 | InstructionSet::SuperChip => true,
+            InstructionSet::XoChip => false,
+        }
+    }
+}
+
+impl Variant for XoChip {
+    fn name(&self) -> &'static str { "xochip" }
+    fn memory_size(&self) -> usize { XO_MEMORY_SIZE }
+    fn display_size(&self) -> (usize, usize) { (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT) }
+    fn quirks(&self) -> Quirks { Quirks::XO_CHIP }
+
+    fn supports(&self, _instruction: &Instruction) -> bool {
+        true
+    }
+}
+
+/// Every built-in variant, oldest first.
+pub const VARIANTS: [&dyn Variant; 5] = [&Chip8, &Chip8X, &Chip48, &SuperChip, &XoChip];
+
+/// Looks up a variant by its `name`.
+pub fn variant(name: &str) -> Option<&'static dyn Variant> {
+    let name = name.to_ascii_lowercase();
+    VARIANTS.iter().copied().find(|variant| variant.name() == name)
+}
+
+/// Guesses the platform a ROM was written for from the newest instructions
+/// reachable from 0x200, or from its size if it only fits in XO-CHIP memory.
+pub fn suggest_variant(rom: &[u8]) -> &'static dyn Variant {
+    if rom.len() > MAX_PROGRAM_SIZE {
+        return &XoChip;
+    }
+
+    let newest = disassembler::disassemble(rom).iter()
+        .filter_map(|line| match line {
+            Line::Code { instruction, .. } => Some(instruction.instruction_set()),
+            Line::Data { .. } => None,
+        })
+        .max()
+        .unwrap_or(InstructionSet::Chip8);
+
+    match newest {
+        InstructionSet::Chip8 => &Chip8,
+        InstructionSet::SuperChip => &SuperChip,
+        InstructionSet::XoChip => &XoChip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ CPU, Chip8Error };
+
+    #[test]
+    fn test_variant_names() {
+        for variant in VARIANTS.iter() {
+            assert_eq!(super::variant(variant.name()), Some(*variant));
+        }
+
+        assert_eq!(super::variant("SCHIP").map(|v| v.name()), Some("schip"));
+        assert!(super::variant("megachip").is_none());
+    }
+
+    #[test]
+    fn test_supported_instructions() {
+        assert!(Chip8.supports(&Instruction::Clear));
+        assert!(!Chip8.supports(&Instruction::HighRes));
+        assert!(!Chip48.supports(&Instruction::Exit));
+        assert!(SuperChip.supports(&Instruction::HighRes));
+        assert!(!SuperChip.supports(&Instruction::StoreILong));
+        assert!(XoChip.supports(&Instruction::StoreILong));
+        assert!(Chip8.supports(&Instruction::JumpV0 { addr: 0x300 }));
+        assert!(!Chip8X.supports(&Instruction::JumpV0 { addr: 0x300 }));
+        assert!(Chip8X.supports(&Instruction::Clear));
+        assert!(!Chip8X.supports(&Instruction::HighRes));
+    }
+
+    #[test]
+    fn test_cpu_with_variant() {
+        let chip8 = CPU::with_variant(&XoChip);
+
+        assert_eq!(chip8.memory_size(), XO_MEMORY_SIZE);
+        assert_eq!(chip8.quirks(), Quirks::XO_CHIP);
+        assert_eq!(chip8.variant().map(|v| v.name()), Some("xochip"));
+        assert!(CPU::default().variant().is_none());
+    }
+
+    #[test]
+    fn test_unsupported_instruction() {
+        let mut chip8 = CPU::with_variant(&Chip8);
+
+        chip8.load_asm("
+            LD V0, 0x01
+            HIGH
+        ");
+
+        assert_eq!(chip8.run(), Err(Chip8Error::UnsupportedOpcode { opcode: 0x00FF, addr: 0x202, variant: "chip8" }));
+        assert_eq!(chip8.registers[0], 1);
+        assert!(!chip8.is_hires());
+    }
+
+    #[derive(Debug)]
+    struct LowResSuperChip;
+
+    impl Variant for LowResSuperChip {
+        fn name(&self) -> &'static str { "lowres" }
+        fn memory_size(&self) -> usize { MEMORY_SIZE }
+        fn display_size(&self) -> (usize, usize) { (DISPLAY_WIDTH, DISPLAY_HEIGHT) }
+        fn quirks(&self) -> Quirks { Quirks::SUPER_CHIP }
+
+        fn supports(&self, instruction: &Instruction) -> bool {
+            SuperChip.supports(instruction)
+        }
+    }
+
+    #[test]
+    fn test_cpu_honours_display_size() {
+        assert_eq!(CPU::with_variant(&Chip8).display_size(), (DISPLAY_WIDTH, DISPLAY_HEIGHT));
+        assert_eq!(CPU::with_variant(&SuperChip).display_size(), (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT));
+        assert_eq!(CPU::default().display_size(), (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT));
+
+        let mut chip8 = CPU::with_variant(&LowResSuperChip);
+
+        chip8.load_asm("
+            HIGH
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        assert!(!chip8.is_hires());
+        assert_eq!(chip8.framebuffer().width(), DISPLAY_WIDTH);
+    }
+
+    #[test]
+    fn test_suggest_variant() {
+        assert_eq!(suggest_variant(&[0x60, 0x05, 0x12, 0x02]).name(), "chip8");
+        assert_eq!(suggest_variant(&[0x00, 0xFF, 0x00, 0xFD]).name(), "schip");
+        assert_eq!(suggest_variant(&[0xF0, 0x00, 0x02, 0x00, 0x00, 0xFD]).name(), "xochip");
+        assert_eq!(suggest_variant(&vec![0; MAX_PROGRAM_SIZE + 1]).name(), "xochip");
+    }
+
+    #[test]
+    fn test_suggest_ignores_data() {
+        // 00FF after the jump is sprite data, not a switch to high resolution.
+        assert_eq!(suggest_variant(&[0x12, 0x00, 0x00, 0xFF]).name(), "chip8");
+    }
+}
@@ -0,0 +1,216 @@
+use crate::cpu::{ Instruction, PROGRAM_START_ADDR };
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Line {
+    Code { addr: usize, opcode: u16, instruction: Instruction },
+    Data { addr: usize, byte: u8 },
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Code { addr, opcode, instruction } =>
+                write!(f, "{:04x}: {:04x}  {}", addr, opcode, instruction),
+            Line::Data { addr, byte } =>
+                write!(f, "{:04x}: {:02x}    DB {:#04x}", addr, byte, byte),
+        }
+    }
+}
+
+fn fetch(rom: &[u8], addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(PROGRAM_START_ADDR)?;
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+
+    Some((rom[offset] as u16) << 8 | rom[offset + 1] as u16)
+}
+
+// Addresses execution can continue at after `instruction` at `addr`. Jumps
+// through V0 are computed at run time, so tracing stops there. Skips hop
+// over the whole of a four byte F000 nnnn.
+fn successors(rom: &[u8], addr: usize, instruction: &Instruction) -> Vec<usize> {
+    let next = addr + 2;
+    let after_next = if fetch(rom, next) == Some(0xF000) { next + 4 } else { next + 2 };
+
+    match *instruction {
+        Instruction::StoreILong => vec![addr + 4],
+        Instruction::Return | Instruction::Exit | Instruction::JumpV0 { .. } => vec![],
+        Instruction::Jump { addr } => vec![addr as usize],
+        Instruction::Call { addr } => vec![addr as usize, next],
+        Instruction::SkipIfEqual { .. } |
+        Instruction::SkipIfNotEqual { .. } |
+        Instruction::SkipIfRegistersEqual { .. } |
+        Instruction::SkipIfRegistersNotEqual { .. } |
+        Instruction::SkipIfKeyPressed { .. } |
+        Instruction::SkipIfKeyNotPressed { .. } => vec![next, after_next],
+        _ => vec![next],
+    }
+}
+
+/// Marks which bytes of `rom` are reachable as instructions by following
+/// every branch from 0x200. Returns one flag per byte, set on the first byte
+/// of each instruction found.
+pub fn trace(rom: &[u8]) -> Vec<bool> {
+    let mut code = vec![false; rom.len()];
+    let mut pending = vec![PROGRAM_START_ADDR];
+
+    while let Some(addr) = pending.pop() {
+        let offset = match addr.checked_sub(PROGRAM_START_ADDR) {
+            Some(offset) if offset < rom.len() && !code[offset] => offset,
+            _ => continue,
+        };
+
+        let instruction = match fetch(rom, addr).map(Instruction::decode) {
+            Some(Ok(instruction)) => instruction,
+            _ => continue,
+        };
+
+        code[offset] = true;
+        pending.extend(successors(rom, addr, &instruction));
+    }
+
+    code
+}
+
+pub fn disassemble(rom: &[u8]) -> Vec<Line> {
+    let code = trace(rom);
+    let mut lines = Vec::new();
+
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = PROGRAM_START_ADDR + offset;
+
+        match fetch(rom, addr) {
+            Some(opcode) if code[offset] => {
+                let instruction = Instruction::decode(opcode).unwrap();
+                lines.push(Line::Code { addr, opcode, instruction });
+                offset += 2;
+            },
+            _ => {
+                lines.push(Line::Data { addr, byte: rom[offset] });
+                offset += 1;
+            },
+        }
+    }
+
+    lines
+}
+
+/// Decodes every aligned word as an instruction without tracing control flow,
+/// falling back to data for words that are not valid opcodes.
+pub fn disassemble_linear(rom: &[u8]) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    for (index, word) in rom.chunks(2).enumerate() {
+        let addr = PROGRAM_START_ADDR + index * 2;
+
+        match fetch(rom, addr).map(|opcode| (opcode, Instruction::decode(opcode))) {
+            Some((opcode, Ok(instruction))) => lines.push(Line::Code { addr, opcode, instruction }),
+            _ => {
+                for (i, &byte) in word.iter().enumerate() {
+                    lines.push(Line::Data { addr: addr + i, byte });
+                }
+            },
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_straight_line() {
+        let rom = [0x60, 0x05, 0x80, 0x14, 0x00, 0xFD];
+        let lines = disassemble(&rom);
+
+        assert_eq!(lines, vec![
+            Line::Code { addr: 0x200, opcode: 0x6005, instruction: Instruction::Store { x: 0, byte: 5 } },
+            Line::Code { addr: 0x202, opcode: 0x8014, instruction: Instruction::Add { x: 0, y: 1 } },
+            Line::Code { addr: 0x204, opcode: 0x00FD, instruction: Instruction::Exit },
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_separates_data_after_jump() {
+        let rom = [
+            0xA2, 0x06,
+            0x12, 0x08,
+            0xFF, 0xFF,
+            0xBA, 0x7C,
+            0xD0, 0x12,
+            0x12, 0x08,
+        ];
+        let lines = disassemble(&rom);
+
+        assert!(lines.contains(&Line::Code { addr: 0x202, opcode: 0x1208, instruction: Instruction::Jump { addr: 0x208 } }));
+        assert!(lines.contains(&Line::Data { addr: 0x204, byte: 0xFF }));
+        assert!(lines.contains(&Line::Data { addr: 0x206, byte: 0xBA }));
+        assert!(lines.contains(&Line::Data { addr: 0x207, byte: 0x7C }));
+        assert!(lines.contains(&Line::Code { addr: 0x208, opcode: 0xD012, instruction: Instruction::Draw { x: 0, y: 1, n: 2 } }));
+    }
+
+    #[test]
+    fn test_disassemble_follows_calls_and_skips() {
+        let rom = [
+            0x22, 0x08,
+            0x30, 0x01,
+            0x12, 0x0C,
+            0x00, 0xFD,
+            0x00, 0xEE,
+            0x01, 0x02,
+            0x00, 0xFD,
+        ];
+        let code = trace(&rom);
+
+        assert_eq!(code, vec![
+            true, false, true, false, true, false, true, false, true, false, false, false, true, false,
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_long_instruction() {
+        let rom = [
+            0x30, 0x01,
+            0xF0, 0x00, 0x12, 0x34,
+            0x00, 0xFD,
+        ];
+        let code = trace(&rom);
+
+        assert_eq!(code, vec![true, false, true, false, false, false, true, false]);
+        assert!(disassemble(&rom).contains(&Line::Data { addr: 0x204, byte: 0x12 }));
+    }
+
+    #[test]
+    fn test_disassemble_odd_trailing_byte() {
+        let rom = [0x00, 0xFD, 0x42];
+        let lines = disassemble(&rom);
+
+        assert_eq!(lines.last(), Some(&Line::Data { addr: 0x202, byte: 0x42 }));
+    }
+
+    #[test]
+    fn test_disassemble_linear() {
+        let rom = [0x60, 0x05, 0xFF, 0xFF];
+        let lines = disassemble_linear(&rom);
+
+        assert_eq!(lines, vec![
+            Line::Code { addr: 0x200, opcode: 0x6005, instruction: Instruction::Store { x: 0, byte: 5 } },
+            Line::Data { addr: 0x202, byte: 0xFF },
+            Line::Data { addr: 0x203, byte: 0xFF },
+        ]);
+    }
+
+    #[test]
+    fn test_line_format() {
+        assert_eq!(
+            Line::Code { addr: 0x200, opcode: 0x6005, instruction: Instruction::Store { x: 0, byte: 5 } }.to_string(),
+            "0200: 6005  LD V0, 0x05");
+        assert_eq!(Line::Data { addr: 0x204, byte: 0xFF }.to_string(), "0204: ff    DB 0xff");
+    }
+}
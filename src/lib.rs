@@ -0,0 +1,8 @@
+pub mod cpu;
+pub mod disassembler;
+pub mod assembler;
+pub mod octo;
+pub mod terminal;
+pub mod capture;
+pub mod sound;
+pub mod rewind;
@@ -1,12 +1,327 @@
-mod cpu;
+use chip8::capture::{ self, GifRecorder };
+use chip8::cpu::{ self, CPU, LoadError, Palette, PALETTE_PRESETS, Quirks, QUIRK_PRESETS, Variant, VARIANTS };
+use chip8::octo;
+use chip8::sound::AudioGenerator;
+use chip8::terminal::Terminal;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::{ Duration, Instant };
+
+const USAGE: &str = "Usage: chip8 <rom.ch8 | source.8o> [options]
+
+Options:
+    --ips <n>           Instructions executed per second (default 500)
+    --seed <n>          Seed for the random number generator
+    --max-cycles <n>    Stop after executing this many instructions
+    --variant <name>    Platform to emulate: chip8, chip8x, chip48, schip or xochip
+                        (default: guessed from the instructions the ROM uses)
+    --quirks <name>     Override the variant's opcode behaviour: vip, chip48, schip or xochip
+    --headless          Do not draw the display (default)
+    --terminal          Play in the terminal: keys 1-4, q-r, a-f and z-v are the keypad,
+                        Esc quits
+    --screenshot <file> Save the display as a PNG when the program stops
+    --record <file>     Record the display as an animated GIF at 20 frames a second
+    --scale <n>         Size in image pixels of each display pixel (default 8)
+    --palette <name>    Image colours: mono or octo (default mono)
+    --wav <file>        Render the buzzer to a WAV file, one 60 Hz tick per frame
+    --load-state <file> Resume from a save state after loading the ROM
+    --save-state <file> Save the machine state when the program stops";
+
+const DEFAULT_SCALE: usize = 8;
+
+#[derive(Debug, PartialEq)]
+enum DisplayMode {
+    Headless,
+    Terminal,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    instructions_per_second: u32,
+    seed: Option<u64>,
+    max_cycles: Option<u64>,
+    variant: Option<&'static dyn Variant>,
+    quirks: Option<Quirks>,
+    display: DisplayMode,
+    screenshot: Option<String>,
+    record: Option<String>,
+    scale: usize,
+    palette: Palette,
+    wav: Option<String>,
+    load_state: Option<String>,
+    save_state: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut rom = None;
+    let mut options = Options {
+        rom: String::new(),
+        instructions_per_second: 500,
+        seed: None,
+        max_cycles: None,
+        variant: None,
+        quirks: None,
+        display: DisplayMode::Headless,
+        screenshot: None,
+        record: None,
+        scale: DEFAULT_SCALE,
+        palette: Palette::default(),
+        wav: None,
+        load_state: None,
+        save_state: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ips" => { options.instructions_per_second = parse_number(&arg, args.next())?; },
+            "--seed" => { options.seed = Some(parse_number(&arg, args.next())?); },
+            "--max-cycles" => { options.max_cycles = Some(parse_number(&arg, args.next())?); },
+            "--variant" => {
+                let name = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                options.variant = Some(cpu::variant(&name).ok_or_else(|| {
+                    let names: Vec<&str> = VARIANTS.iter().map(|variant| variant.name()).collect();
+                    format!("Unknown variant: {} (expected one of {})", name, names.join(", "))
+                })?);
+            },
+            "--quirks" => {
+                let name = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                options.quirks = Some(Quirks::preset(&name).ok_or_else(|| {
+                    format!("Unknown quirks preset: {} (expected one of {})", name, QUIRK_PRESETS.join(", "))
+                })?);
+            },
+            "--screenshot" => { options.screenshot = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?); },
+            "--record" => { options.record = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?); },
+            "--load-state" => { options.load_state = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?); },
+            "--save-state" => { options.save_state = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?); },
+            "--wav" => { options.wav = Some(args.next().ok_or_else(|| format!("{} needs a value", arg))?); },
+            "--scale" => { options.scale = parse_number(&arg, args.next())?; },
+            "--palette" => {
+                let name = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                options.palette = Palette::preset(&name).ok_or_else(|| {
+                    format!("Unknown palette: {} (expected one of {})", name, PALETTE_PRESETS.join(", "))
+                })?;
+            },
+            "--headless" => { options.display = DisplayMode::Headless; },
+            "--terminal" => { options.display = DisplayMode::Terminal; },
+            _ if arg.starts_with("--") => { return Err(format!("Unknown option: {}", arg)); },
+            _ if rom.is_none() => { rom = Some(arg); },
+            _ => { return Err(format!("Unexpected argument: {}", arg)); },
+        }
+    }
+
+    if options.instructions_per_second == 0 {
+        return Err("--ips must be greater than 0".to_string());
+    }
+
+    if options.scale == 0 {
+        return Err("--scale must be greater than 0".to_string());
+    }
+
+    options.rom = rom.ok_or_else(|| "No ROM given".to_string())?;
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let program = if options.rom.ends_with(".8o") {
+        octo::compile_file(&options.rom).map_err(|err| err.to_string())?
+    } else {
+        fs::read(&options.rom).map_err(|err| format!("{}: {}", options.rom, LoadError::from(err)))?
+    };
+
+    let variant = options.variant.unwrap_or_else(|| cpu::suggest_variant(&program));
+    let mut cpu = CPU::with_variant(variant);
+    if let Some(quirks) = options.quirks {
+        cpu.set_quirks(quirks);
+    }
+
+    cpu.load(&program).map_err(|err| format!("{}: {}", options.rom, err))?;
+    cpu.set_instructions_per_second(options.instructions_per_second);
+    if let Some(seed) = options.seed {
+        cpu.set_seed(seed);
+    }
+
+    if let Some(path) = &options.load_state {
+        let state = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        cpu.load_state(&state).map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    let frame = Duration::from_secs(1) / cpu::TIMER_FREQUENCY;
+    let mut total_cycles: u64 = 0;
+
+    let mut recorder = match &options.record {
+        Some(path) => {
+            let (width, height) = cpu.display_size();
+            Some(GifRecorder::create(path, width, height, options.scale, &options.palette)
+                .map_err(|err| format!("{}: {}", path, err))?)
+        },
+        None => None,
+    };
+
+    let mut audio = options.wav.as_ref().map(|_| AudioGenerator::default());
+
+    let mut terminal = match options.display {
+        DisplayMode::Terminal => Some(Terminal::open().map_err(|err| format!("Could not set up the terminal: {}", err))?),
+        DisplayMode::Headless => None,
+    };
+
+    loop {
+        let started = Instant::now();
+
+        if let Some(terminal) = terminal.as_mut() {
+            if !terminal.poll_input(&mut cpu).map_err(|err| err.to_string())? {
+                break;
+            }
+        }
+
+        let executed = match options.max_cycles {
+            Some(max) if max - total_cycles < cpu.frame_cycles() => cpu.run_cycles(max - total_cycles),
+            _ => cpu.run_frame(),
+        };
+        total_cycles += executed.map_err(|err| err.to_string())?;
+
+        if let Some(audio) = audio.as_mut() {
+            audio.tick(&cpu);
+        }
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&cpu.framebuffer()).map_err(|err| err.to_string())?;
+        }
+
+        if let Some(terminal) = terminal.as_mut() {
+            terminal.draw(&mut cpu).map_err(|err| err.to_string())?;
+        }
+
+        // With a keyboard attached, a program waiting for a key is still running.
+        let stopped = cpu.is_halted() || (terminal.is_none() && cpu.is_waiting_for_key());
+        if stopped || Some(total_cycles) == options.max_cycles {
+            break;
+        }
+
+        if let Some(remaining) = frame.checked_sub(started.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+
+    drop(terminal);
+
+    if let Some(recorder) = recorder {
+        let path = options.record.as_ref().unwrap();
+        recorder.finish().map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    if let (Some(audio), Some(path)) = (audio, &options.wav) {
+        audio.save_wav(path).map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    if let Some(path) = &options.save_state {
+        fs::write(path, cpu.save_state()).map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    if let Some(path) = &options.screenshot {
+        capture::save_png(path, &cpu.framebuffer(), options.scale, &options.palette)
+            .map_err(|err| format!("{}: {}", path, err))?;
+    }
+
+    if cpu.is_waiting_for_key() {
+        eprintln!("Stopped waiting for a key press after {} cycles", total_cycles);
+    } else if let Some(addr) = cpu.sys_call() {
+        eprintln!("Trapped SYS {:03x} after {} cycles", addr, total_cycles);
+    } else {
+        eprintln!("Stopped after {} cycles", total_cycles);
+    }
+
+    Ok(())
+}
 
 fn main() {
-    let mut c = cpu::CPU::new();
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
+    if let Err(err) = run(&options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        list.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let options = parse_args(args(&["pong.ch8"])).unwrap();
+
+        assert_eq!(options.rom, "pong.ch8");
+        assert_eq!(options.instructions_per_second, 500);
+        assert_eq!(options.seed, None);
+        assert_eq!(options.max_cycles, None);
+        assert_eq!(options.variant, None);
+        assert_eq!(options.quirks, None);
+        assert_eq!(options.display, DisplayMode::Headless);
+        assert_eq!(options.screenshot, None);
+        assert_eq!(options.record, None);
+        assert_eq!(options.scale, 8);
+        assert_eq!(options.palette, Palette::MONOCHROME);
+        assert_eq!(options.wav, None);
+        assert_eq!(options.load_state, None);
+        assert_eq!(options.save_state, None);
+    }
 
-    let mem: [u8; 3176] = [0; 3176];
+    #[test]
+    fn test_parse_all_options() {
+        let options = parse_args(args(&[
+            "--ips", "1000", "--seed", "42", "pong.ch8", "--max-cycles", "5000", "--terminal",
+            "--quirks", "schip", "--variant", "XOCHIP", "--screenshot", "end.png", "--record", "run.gif",
+            "--scale", "4", "--palette", "octo", "--wav", "beep.wav", "--load-state", "in.state",
+            "--save-state", "out.state",
+        ])).unwrap();
 
-    c.load(mem);
-    c.run();
+        assert_eq!(options.rom, "pong.ch8");
+        assert_eq!(options.instructions_per_second, 1000);
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.max_cycles, Some(5000));
+        assert_eq!(options.variant.map(|variant| variant.name()), Some("xochip"));
+        assert_eq!(options.quirks, Some(Quirks::SUPER_CHIP));
+        assert_eq!(options.display, DisplayMode::Terminal);
+        assert_eq!(options.screenshot, Some("end.png".to_string()));
+        assert_eq!(options.record, Some("run.gif".to_string()));
+        assert_eq!(options.scale, 4);
+        assert_eq!(options.palette, Palette::OCTO);
+        assert_eq!(options.wav, Some("beep.wav".to_string()));
+        assert_eq!(options.load_state, Some("in.state".to_string()));
+        assert_eq!(options.save_state, Some("out.state".to_string()));
+    }
 
-    println!("Hello, world!");
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_args(args(&[])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--ips"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--ips", "fast"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--ips", "0"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--colour"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--quirks", "megachip"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--variant", "megachip"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--palette", "sepia"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--scale", "0"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "--record"])).is_err());
+        assert!(parse_args(args(&["pong.ch8", "tetris.ch8"])).is_err());
+    }
 }
@@ -0,0 +1,1157 @@
+use crate::assembler::{ self, AssembleError };
+use crate::cpu::{ Instruction, MAX_XO_PROGRAM_SIZE, PROGRAM_START_ADDR };
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const SOURCE_NAME: &str = "<source>";
+const MAIN_LABEL: &str = "main";
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+// Register clobbered by the `<`, `>`, `<=` and `>=` pseudo-ops.
+const COMPARE_TEMP: u8 = 0xF;
+
+const KEYWORDS: &[&str] = &[
+    "clear", "return", ";", "exit", "native", "jump", "jump0", "sprite", "save", "load", "bcd",
+    "scroll-down", "scroll-up", "scroll-right", "scroll-left", "lores", "hires", "saveflags", "loadflags",
+    "plane", "audio", "pitch", "long",
+    "delay", "buzzer", "i", "hex", "bighex", "random", "key", "-key", "if", "then", "begin", "else", "end",
+    "loop", "again", "while", ":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<=",
+    "==", "!=", "<", ">", "<=", ">=", "{", "}", "HERE",
+];
+
+const CALC_OPERATORS: &[&str] = &["+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>"];
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+    Less(u8, Operand),
+    Greater(u8, Operand),
+    LessEqual(u8, Operand),
+    GreaterEqual(u8, Operand),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::Equal(x, operand) => Condition::NotEqual(x, operand),
+            Condition::NotEqual(x, operand) => Condition::Equal(x, operand),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+            Condition::Less(x, operand) => Condition::GreaterEqual(x, operand),
+            Condition::GreaterEqual(x, operand) => Condition::Less(x, operand),
+            Condition::Greater(x, operand) => Condition::LessEqual(x, operand),
+            Condition::LessEqual(x, operand) => Condition::Greater(x, operand),
+        }
+    }
+}
+
+// How a label that was used before its definition is patched in.
+#[derive(Clone, Copy, Debug)]
+enum FixupKind {
+    Addr,
+    // The 16-bit word following `i := long`.
+    Long,
+    UnpackHigh(u8),
+    UnpackLow,
+}
+
+#[derive(Clone, Debug)]
+struct Fixup {
+    addr: usize,
+    name: String,
+    kind: FixupKind,
+    line: usize,
+}
+
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+    line: usize,
+}
+
+struct Branch {
+    jump: usize,
+    has_else: bool,
+    line: usize,
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    pos: usize,
+    image: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    branches: Vec<Branch>,
+    expansions: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (index, raw) in source.lines().enumerate() {
+        let text = match raw.find('#') {
+            Some(comment) => &raw[..comment],
+            None => raw,
+        };
+
+        for word in text.split_whitespace() {
+            tokens.push(Token { text: word.to_string(), line: index + 1 });
+        }
+    }
+
+    tokens
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('v') | Some('V') => {},
+        _ => return None,
+    }
+
+    let digit = chars.next()?.to_digit(16)?;
+    if chars.next().is_some() {
+        return None;
+    }
+
+    Some(digit as u8)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    match text.strip_prefix('-') {
+        Some(rest) => assembler::parse_number(rest).map(|number| -number),
+        None => assembler::parse_number(text),
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    !text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == ':') &&
+        parse_register(text).is_none() &&
+        !KEYWORDS.contains(&text)
+}
+
+impl Compiler {
+    fn new(source: &str, file: &str) -> Self {
+        Compiler {
+            file: file.to_string(),
+            tokens: tokenize(source),
+            pos: 0,
+            image: Vec::new(),
+            here: PROGRAM_START_ADDR,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn error<T, M: Into<String>>(&self, line: usize, message: M) -> Result<T, AssembleError> {
+        Err(AssembleError { file: self.file.clone(), line, message: message.into() })
+    }
+
+    // Line of the most recently read token.
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos.saturating_sub(1)).map_or(0, |token| token.line)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<Token, AssembleError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            },
+            None => self.error(self.line(), "Unexpected end of file"),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        if token.text != text {
+            return self.error(token.line, format!("Expected `{}` but found `{}`", text, token.text));
+        }
+
+        Ok(())
+    }
+
+    fn register_of(&self, token: &Token) -> Option<u8> {
+        parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+    }
+
+    fn register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        match self.register_of(&token) {
+            Some(register) => Ok(register),
+            None => self.error(token.line, format!("Expected a register but found `{}`", token.text)),
+        }
+    }
+
+    fn value_of(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|&addr| addr as i64))
+    }
+
+    fn fit(&self, value: i64, min: i64, max: i64, what: &str, line: usize) -> Result<i64, AssembleError> {
+        if value < min || value > max {
+            return self.error(line, format!("{} does not fit in {}", value, what));
+        }
+
+        Ok(value)
+    }
+
+    fn number(&mut self, min: i64, max: i64, what: &str) -> Result<i64, AssembleError> {
+        let token = self.next()?;
+        match self.value_of(&token) {
+            Some(value) => self.fit(value, min, max, what, token.line),
+            None if is_name(&token.text) => self.error(token.line, format!("Undefined name `{}`", token.text)),
+            None => self.error(token.line, format!("Invalid value `{}`", token.text)),
+        }
+    }
+
+    // Negative bytes are accepted as their two's complement.
+    fn byte(&mut self) -> Result<u8, AssembleError> {
+        Ok(self.number(-128, 0xFF, "a byte")? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssembleError> {
+        Ok(self.number(0, 0xF, "a nibble")? as u8)
+    }
+
+    fn operand(&mut self) -> Result<Operand, AssembleError> {
+        let register = self.tokens.get(self.pos).and_then(|token| self.register_of(token));
+        match register {
+            Some(register) => {
+                self.pos += 1;
+                Ok(Operand::Register(register))
+            },
+            None => Ok(Operand::Byte(self.byte()?)),
+        }
+    }
+
+    // Reads an address for the instruction about to be emitted. Labels may be
+    // used before they are defined; those are patched in by `finish`.
+    fn addr(&mut self) -> Result<u16, AssembleError> {
+        let token = self.next()?;
+        match self.value_of(&token) {
+            Some(value) => Ok(self.fit(value, 0, 0xFFF, "an address", token.line)? as u16),
+            None if is_name(&token.text) => {
+                self.fixups.push(Fixup { addr: self.here, name: token.text, kind: FixupKind::Addr, line: token.line });
+                Ok(0)
+            },
+            None => self.error(token.line, format!("Invalid value `{}`", token.text)),
+        }
+    }
+
+    // Like `addr`, but for the full 16-bit word emitted after `i := long`.
+    fn long_addr(&mut self) -> Result<u16, AssembleError> {
+        let token = self.next()?;
+        match self.value_of(&token) {
+            Some(value) => Ok(self.fit(value, 0, 0xFFFF, "a long address", token.line)? as u16),
+            None if is_name(&token.text) => {
+                self.fixups.push(Fixup { addr: self.here, name: token.text, kind: FixupKind::Long, line: token.line });
+                Ok(0)
+            },
+            None => self.error(token.line, format!("Invalid value `{}`", token.text)),
+        }
+    }
+
+    fn define_name(&self, token: &Token) -> Result<(), AssembleError> {
+        if !is_name(&token.text) {
+            return self.error(token.line, format!("`{}` is not a valid name", token.text));
+        }
+
+        if self.labels.contains_key(&token.text) || self.constants.contains_key(&token.text) ||
+            self.aliases.contains_key(&token.text) || self.macros.contains_key(&token.text) {
+            return self.error(token.line, format!("`{}` is already defined", token.text));
+        }
+
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssembleError> {
+        let offset = self.here - PROGRAM_START_ADDR;
+        if offset >= MAX_XO_PROGRAM_SIZE {
+            return self.error(self.line(), format!("Program does not fit in the {} bytes of memory", MAX_XO_PROGRAM_SIZE));
+        }
+
+        if offset >= self.image.len() {
+            self.image.resize(offset + 1, 0);
+        }
+
+        self.image[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), AssembleError> {
+        for byte in instruction.encode().to_be_bytes().iter() {
+            self.emit_byte(*byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, addr: usize, target: usize, line: usize) -> Result<(), AssembleError> {
+        self.fit(target as i64, 0, 0xFFF, "an address", line)?;
+
+        let opcode = Instruction::Jump { addr: target as u16 }.encode().to_be_bytes();
+        let offset = addr - PROGRAM_START_ADDR;
+        self.image[offset..offset + 2].copy_from_slice(&opcode);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+
+        if let Some(body) = self.macros.get(&token.text).cloned() {
+            return self.expand(&token, body);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define_name(&name)?;
+                self.labels.insert(name.text, self.here);
+            },
+            ":const" => {
+                let name = self.next()?;
+                self.define_name(&name)?;
+                let value = self.number(i64::MIN, i64::MAX, "a constant")?;
+                self.constants.insert(name.text, value);
+            },
+            ":alias" => {
+                let name = self.next()?;
+                if !self.aliases.contains_key(&name.text) {
+                    self.define_name(&name)?;
+                }
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            },
+            ":calc" => {
+                let name = self.next()?;
+                self.define_name(&name)?;
+                let value = self.braced_calc()?;
+                self.constants.insert(name.text, value);
+            },
+            ":byte" => {
+                let byte = if self.peek() == Some("{") {
+                    let value = self.braced_calc()?;
+                    self.fit(value, -128, 0xFF, "a byte", token.line)? as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit_byte(byte)?;
+            },
+            // 0x200 holds the jump to `main`, so code can only start after it.
+            ":org" => {
+                let min = (PROGRAM_START_ADDR + 2) as i64;
+                let max = (PROGRAM_START_ADDR + MAX_XO_PROGRAM_SIZE - 1) as i64;
+                self.here = self.number(min, max, "the program area")? as usize;
+            },
+            ":unpack" => { self.unpack()?; },
+            ":macro" => { self.define_macro()?; },
+            ":call" => {
+                let addr = self.addr()?;
+                self.emit(Instruction::Call { addr })?;
+            },
+            ":breakpoint" => { self.next()?; },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            "clear" => { self.emit(Instruction::Clear)?; },
+            "return" | ";" => { self.emit(Instruction::Return)?; },
+            "exit" => { self.emit(Instruction::Exit)?; },
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown { n })?;
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp { n })?;
+            },
+            "scroll-right" => { self.emit(Instruction::ScrollRight)?; },
+            "scroll-left" => { self.emit(Instruction::ScrollLeft)?; },
+            "lores" => { self.emit(Instruction::LowRes)?; },
+            "hires" => { self.emit(Instruction::HighRes)?; },
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags { x })?;
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x })?;
+            },
+            "plane" => {
+                let n = self.number(0, 3, "a plane mask")? as u8;
+                self.emit(Instruction::SelectPlanes { n })?;
+            },
+            "audio" => { self.emit(Instruction::LoadAudioPattern)?; },
+            "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(Instruction::SetPitch { x })?;
+            },
+            "native" => {
+                let addr = self.addr()?;
+                let instruction = assembler::sys(addr).or_else(|message| self.error(token.line, message))?;
+                self.emit(instruction)?;
+            },
+            "jump" => {
+                let addr = self.addr()?;
+                self.emit(Instruction::Jump { addr })?;
+            },
+            "jump0" => {
+                let addr = self.addr()?;
+                self.emit(Instruction::JumpV0 { addr })?;
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n })?;
+            },
+            "save" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    self.emit(Instruction::StoreRange { x, y })?;
+                } else {
+                    self.emit(Instruction::StoreRegisters { x })?;
+                }
+            },
+            "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    self.emit(Instruction::LoadRange { x, y })?;
+                } else {
+                    self.emit(Instruction::LoadRegisters { x })?;
+                }
+            },
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd { x })?;
+            },
+            "delay" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(Instruction::SetDelayTimer { x })?;
+            },
+            "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(Instruction::SetSoundTimer { x })?;
+            },
+            "i" => { self.assign_i()?; },
+            "if" => { self.conditional(token.line)?; },
+            "else" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) if !branch.has_else => branch,
+                    _ => return self.error(token.line, "`else` without a matching `begin`"),
+                };
+
+                let jump = self.here;
+                self.emit(Instruction::Jump { addr: 0 })?;
+                self.patch_jump(branch.jump, self.here, token.line)?;
+                self.branches.push(Branch { jump, has_else: true, line: branch.line });
+            },
+            "end" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.error(token.line, "`end` without a matching `begin`"),
+                };
+
+                self.patch_jump(branch.jump, self.here, token.line)?;
+            },
+            "loop" => {
+                self.loops.push(Loop { start: self.here, breaks: Vec::new(), line: token.line });
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error(token.line, "`while` outside of a loop");
+                }
+
+                let condition = self.condition()?;
+                self.skip_unless(condition.negate())?;
+
+                let jump = self.here;
+                self.emit(Instruction::Jump { addr: 0 })?;
+                self.loops.last_mut().unwrap().breaks.push(jump);
+            },
+            "again" => {
+                let lp = match self.loops.pop() {
+                    Some(lp) => lp,
+                    None => return self.error(token.line, "`again` without a matching `loop`"),
+                };
+
+                let start = self.fit(lp.start as i64, 0, 0xFFF, "an address", token.line)?;
+                self.emit(Instruction::Jump { addr: start as u16 })?;
+                for jump in lp.breaks {
+                    self.patch_jump(jump, self.here, token.line)?;
+                }
+            },
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    return self.assign_register(x);
+                }
+
+                if let Some(&addr) = self.labels.get(&token.text) {
+                    return self.emit(Instruction::Call { addr: addr as u16 });
+                }
+
+                match self.value_of(&token) {
+                    Some(value) => {
+                        let byte = self.fit(value, -128, 0xFF, "a byte", token.line)? as u8;
+                        self.emit_byte(byte)?;
+                    },
+                    None if is_name(&token.text) => {
+                        // A bare name calls a subroutine that may be defined later.
+                        self.pos -= 1;
+                        let addr = self.addr()?;
+                        self.emit(Instruction::Call { addr })?;
+                    },
+                    None => return self.error(token.line, format!("Unexpected `{}`", token.text)),
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn assign_register(&mut self, x: u8) -> Result<(), AssembleError> {
+        let op = self.next()?;
+
+        let instruction = match op.text.as_str() {
+            ":=" => match self.peek() {
+                Some("delay") => {
+                    self.pos += 1;
+                    Instruction::LoadDelayTimer { x }
+                },
+                Some("key") => {
+                    self.pos += 1;
+                    Instruction::LoadKeyPress { x }
+                },
+                Some("random") => {
+                    self.pos += 1;
+                    Instruction::Random { x, byte: self.byte()? }
+                },
+                _ => match self.operand()? {
+                    Operand::Register(y) => Instruction::Copy { x, y },
+                    Operand::Byte(byte) => Instruction::Store { x, byte },
+                },
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(y) => Instruction::Add { x, y },
+                Operand::Byte(byte) => Instruction::AddValue { x, byte },
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => Instruction::Sub { x, y },
+                Operand::Byte(byte) => Instruction::AddValue { x, byte: byte.wrapping_neg() },
+            },
+            "=-" => Instruction::Subn { x, y: self.register()? },
+            "|=" => Instruction::Or { x, y: self.register()? },
+            "&=" => Instruction::And { x, y: self.register()? },
+            "^=" => Instruction::Xor { x, y: self.register()? },
+            ">>=" => Instruction::ShiftRight { x, y: self.register()? },
+            "<<=" => Instruction::ShiftLeft { x, y: self.register()? },
+            _ => return self.error(op.line, format!("Expected an assignment but found `{}`", op.text)),
+        };
+
+        self.emit(instruction)
+    }
+
+    fn assign_i(&mut self) -> Result<(), AssembleError> {
+        let op = self.next()?;
+
+        let instruction = match op.text.as_str() {
+            ":=" if self.peek() == Some("hex") => {
+                self.pos += 1;
+                Instruction::LoadSpriteAddr { x: self.register()? }
+            },
+            ":=" if self.peek() == Some("bighex") => {
+                self.pos += 1;
+                Instruction::LoadLargeSpriteAddr { x: self.register()? }
+            },
+            ":=" if self.peek() == Some("long") => {
+                self.pos += 1;
+                self.emit(Instruction::StoreILong)?;
+                let addr = self.long_addr()?.to_be_bytes();
+                self.emit_byte(addr[0])?;
+                return self.emit_byte(addr[1]);
+            },
+            ":=" => Instruction::StoreI { addr: self.addr()? },
+            "+=" => Instruction::AddToI { x: self.register()? },
+            _ => return self.error(op.line, format!("Expected `:=` or `+=` after `i` but found `{}`", op.text)),
+        };
+
+        self.emit(instruction)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = self.register()?;
+        let op = self.next()?;
+
+        let condition = match op.text.as_str() {
+            "key" => Condition::Key(x),
+            "-key" => Condition::NotKey(x),
+            "==" => Condition::Equal(x, self.operand()?),
+            "!=" => Condition::NotEqual(x, self.operand()?),
+            "<" => Condition::Less(x, self.operand()?),
+            ">" => Condition::Greater(x, self.operand()?),
+            "<=" => Condition::LessEqual(x, self.operand()?),
+            ">=" => Condition::GreaterEqual(x, self.operand()?),
+            _ => return self.error(op.line, format!("Expected a comparison but found `{}`", op.text)),
+        };
+
+        Ok(condition)
+    }
+
+    fn conditional(&mut self, line: usize) -> Result<(), AssembleError> {
+        let condition = self.condition()?;
+        let token = self.next()?;
+
+        match token.text.as_str() {
+            "then" => self.skip_unless(condition),
+            "begin" => {
+                self.skip_unless(condition.negate())?;
+
+                let jump = self.here;
+                self.emit(Instruction::Jump { addr: 0 })?;
+                self.branches.push(Branch { jump, has_else: false, line });
+                Ok(())
+            },
+            _ => self.error(token.line, format!("Expected `then` or `begin` but found `{}`", token.text)),
+        }
+    }
+
+    // Leaves VF set to 1 when `x >= operand`, or when `operand >= x` if
+    // `reversed`, using the no-borrow flag of a subtraction.
+    fn compare(&mut self, x: u8, operand: Operand, reversed: bool) -> Result<(), AssembleError> {
+        if x == COMPARE_TEMP {
+            return self.error(self.line(), "vf cannot be compared with `<`, `>`, `<=` or `>=`");
+        }
+
+        match operand {
+            Operand::Register(y) => self.emit(Instruction::Copy { x: COMPARE_TEMP, y })?,
+            Operand::Byte(byte) => self.emit(Instruction::Store { x: COMPARE_TEMP, byte })?,
+        }
+
+        if reversed {
+            self.emit(Instruction::Sub { x: COMPARE_TEMP, y: x })
+        } else {
+            self.emit(Instruction::Subn { x: COMPARE_TEMP, y: x })
+        }
+    }
+
+    // Emits code that skips the next instruction unless `condition` holds.
+    fn skip_unless(&mut self, condition: Condition) -> Result<(), AssembleError> {
+        let instruction = match condition {
+            Condition::Equal(x, Operand::Byte(byte)) => Instruction::SkipIfNotEqual { x, byte },
+            Condition::Equal(x, Operand::Register(y)) => Instruction::SkipIfRegistersNotEqual { x, y },
+            Condition::NotEqual(x, Operand::Byte(byte)) => Instruction::SkipIfEqual { x, byte },
+            Condition::NotEqual(x, Operand::Register(y)) => Instruction::SkipIfRegistersEqual { x, y },
+            Condition::Key(x) => Instruction::SkipIfKeyNotPressed { x },
+            Condition::NotKey(x) => Instruction::SkipIfKeyPressed { x },
+            Condition::Less(x, operand) => {
+                self.compare(x, operand, false)?;
+                Instruction::SkipIfNotEqual { x: COMPARE_TEMP, byte: 0 }
+            },
+            Condition::GreaterEqual(x, operand) => {
+                self.compare(x, operand, false)?;
+                Instruction::SkipIfEqual { x: COMPARE_TEMP, byte: 0 }
+            },
+            Condition::Greater(x, operand) => {
+                self.compare(x, operand, true)?;
+                Instruction::SkipIfNotEqual { x: COMPARE_TEMP, byte: 0 }
+            },
+            Condition::LessEqual(x, operand) => {
+                self.compare(x, operand, true)?;
+                Instruction::SkipIfEqual { x: COMPARE_TEMP, byte: 0 }
+            },
+        };
+
+        self.emit(instruction)
+    }
+
+    // `:unpack n name` loads v0 with `n` and the high nibble of `name`, and
+    // v1 with its low byte.
+    fn unpack(&mut self) -> Result<(), AssembleError> {
+        let nibble = self.nibble()?;
+        let token = self.next()?;
+
+        let addr = match self.value_of(&token) {
+            Some(value) => self.fit(value, 0, 0xFFF, "an address", token.line)? as u16,
+            None if is_name(&token.text) => {
+                self.fixups.push(Fixup { addr: self.here, name: token.text.clone(), kind: FixupKind::UnpackHigh(nibble), line: token.line });
+                self.fixups.push(Fixup { addr: self.here + 2, name: token.text, kind: FixupKind::UnpackLow, line: token.line });
+                0
+            },
+            None => return self.error(token.line, format!("Invalid value `{}`", token.text)),
+        };
+
+        self.emit(Instruction::Store { x: 0, byte: nibble << 4 | (addr >> 8) as u8 })?;
+        self.emit(Instruction::Store { x: 1, byte: addr as u8 })
+    }
+
+    fn define_macro(&mut self) -> Result<(), AssembleError> {
+        let name = self.next()?;
+        self.define_name(&name)?;
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = match self.tokens.get(self.pos) {
+                Some(token) => token.clone(),
+                None => return self.error(name.line, format!("Macro `{}` is missing its closing `}}`", name.text)),
+            };
+            self.pos += 1;
+
+            match token.text.as_str() {
+                "{" => { depth += 1; },
+                "}" if depth == 1 => break,
+                "}" => { depth -= 1; },
+                _ => {},
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    // Splices the macro body into the token stream with its parameters
+    // replaced by the tokens that follow the invocation.
+    fn expand(&mut self, name: &Token, body: Macro) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return self.error(name.line, format!("Too many macro expansions; does `{}` invoke itself?", name.text));
+        }
+
+        let mut args = HashMap::new();
+        for param in body.params.iter() {
+            args.insert(param.clone(), self.next()?.text);
+        }
+
+        let expanded: Vec<Token> = body.body.iter().map(|token| Token {
+            text: args.get(&token.text).cloned().unwrap_or_else(|| token.text.clone()),
+            line: name.line,
+        }).collect();
+
+        self.tokens.splice(self.pos..self.pos, expanded);
+        Ok(())
+    }
+
+    fn braced_calc(&mut self) -> Result<i64, AssembleError> {
+        self.expect("{")?;
+        let value = self.calc()?;
+        self.expect("}")?;
+
+        Ok(value)
+    }
+
+    // Like Octo, `:calc` has no operator precedence and evaluates right to
+    // left, so `2 * 3 + 1` is 8. Parentheses group.
+    fn calc(&mut self) -> Result<i64, AssembleError> {
+        let left = self.calc_term()?;
+
+        let op = match self.peek() {
+            Some(op) if CALC_OPERATORS.contains(&op) => op.to_string(),
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let line = self.line();
+        let right = self.calc()?;
+
+        let value = match op.as_str() {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return self.error(line, "Division by zero"),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "<<" => left.checked_shl(right as u32).unwrap_or(0),
+            _ => left.checked_shr(right as u32).unwrap_or(0),
+        };
+
+        Ok(value)
+    }
+
+    fn calc_term(&mut self) -> Result<i64, AssembleError> {
+        let token = self.next()?;
+
+        let value = match token.text.as_str() {
+            "(" => {
+                let value = self.calc()?;
+                self.expect(")")?;
+                value
+            },
+            "-" => self.calc_term()?.wrapping_neg(),
+            "~" => !self.calc_term()?,
+            "!" => (self.calc_term()? == 0) as i64,
+            "HERE" => self.here as i64,
+            _ => match self.value_of(&token) {
+                Some(value) => value,
+                None => return self.error(token.line, format!("Undefined name `{}`", token.text)),
+            },
+        };
+
+        Ok(value)
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, AssembleError> {
+        if let Some(lp) = self.loops.last() {
+            return self.error(lp.line, "`loop` without a matching `again`");
+        }
+
+        if let Some(branch) = self.branches.last() {
+            return self.error(branch.line, "`begin` without a matching `end`");
+        }
+
+        let main = match self.labels.get(MAIN_LABEL) {
+            Some(&main) => main,
+            None => return self.error(self.line(), "Program has no `main` label"),
+        };
+        let line = self.line();
+        self.patch_jump(PROGRAM_START_ADDR, main, line)?;
+
+        for fixup in self.fixups.iter() {
+            let addr = match self.labels.get(&fixup.name) {
+                Some(&addr) => addr,
+                None => return self.error(fixup.line, format!("Undefined name `{}`", fixup.name)),
+            };
+            match fixup.kind {
+                FixupKind::Long => { self.fit(addr as i64, 0, 0xFFFF, "a long address", fixup.line)?; },
+                _ => { self.fit(addr as i64, 0, 0xFFF, "an address", fixup.line)?; },
+            }
+
+            let offset = fixup.addr - PROGRAM_START_ADDR;
+            match fixup.kind {
+                FixupKind::Addr => {
+                    self.image[offset] = (self.image[offset] & 0xF0) | (addr >> 8) as u8;
+                    self.image[offset + 1] = addr as u8;
+                },
+                FixupKind::Long => {
+                    self.image[offset] = (addr >> 8) as u8;
+                    self.image[offset + 1] = addr as u8;
+                },
+                FixupKind::UnpackHigh(nibble) => { self.image[offset + 1] = nibble << 4 | (addr >> 8) as u8; },
+                FixupKind::UnpackLow => { self.image[offset + 1] = addr as u8; },
+            }
+        }
+
+        Ok(self.image)
+    }
+}
+
+fn compile_in(source: &str, file: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut compiler = Compiler::new(source, file);
+
+    // Execution starts at 0x200, which jumps to `main` wherever it ends up.
+    compiler.emit(Instruction::Jump { addr: 0 })?;
+    while compiler.pos < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+
+    compiler.finish()
+}
+
+/// Compiles Octo source into a program image starting at 0x200.
+pub fn compile(source: &str) -> Result<Vec<u8>, AssembleError> {
+    compile_in(source, SOURCE_NAME)
+}
+
+pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AssembleError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+
+    let source = fs::read_to_string(path).map_err(|err| AssembleError {
+        file: file.clone(),
+        line: 0,
+        message: format!("Could not read file: {}", err),
+    })?;
+
+    compile_in(&source, &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_compile_statements() {
+        let program = compile("
+            : main
+                clear
+                v0 := 5          # load five
+                v0 += v1
+                va -= 1
+                v2 := random 0x0F
+                i := hex v3
+                i += v4
+                delay := v5
+                v6 := key
+                sprite v2 v3 6
+                save vf
+                load v4
+                bcd v1
+                v1 >>= v1
+                return
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0x00, 0xE0,
+            0x60, 0x05,
+            0x80, 0x14,
+            0x7A, 0xFF,
+            0xC2, 0x0F,
+            0xF3, 0x29,
+            0xF4, 0x1E,
+            0xF5, 0x15,
+            0xF6, 0x0A,
+            0xD2, 0x36,
+            0xFF, 0x55,
+            0xF4, 0x65,
+            0xF1, 0x33,
+            0x81, 0x16,
+            0x00, 0xEE,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_super_chip() {
+        let program = compile("
+            : main
+                hires
+                scroll-down 4
+                scroll-right
+                scroll-left
+                i := bighex v3
+                saveflags v7
+                loadflags v7
+                lores
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0x00, 0xFF,
+            0x00, 0xC4,
+            0x00, 0xFB,
+            0x00, 0xFC,
+            0xF3, 0x30,
+            0xF7, 0x75,
+            0xF7, 0x85,
+            0x00, 0xFE,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_xo_chip() {
+        let program = compile("
+            : main
+                i := long data
+                save v1 - v3
+                load v3 - v1
+                plane 3
+                audio
+                pitch := v2
+                scroll-up 5
+            : data
+                0x2A
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0xF0, 0x00, 0x02, 0x12,
+            0x51, 0x32,
+            0x53, 0x13,
+            0xF3, 0x01,
+            0xF0, 0x02,
+            0xF2, 0x3A,
+            0x00, 0xD5,
+            0x2A,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_labels_constants_and_aliases() {
+        let program = compile("
+            :const SPEED 3
+            :alias x v4
+            : main
+                i := ship
+                draw
+                jump main
+            : draw
+                x += SPEED
+                :unpack 0xA ship
+                ;
+            : ship
+                0xBA 0x7C
+                :byte -1
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0xA2, 0x10,
+            0x22, 0x08,
+            0x12, 0x02,
+            0x74, 0x03,
+            0x60, 0xA2,
+            0x61, 0x10,
+            0x00, 0xEE,
+            0xBA, 0x7C, 0xFF,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_control_flow() {
+        let program = compile("
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 key begin
+                    v1 := 3
+                else
+                    v1 := 4
+                end
+                loop
+                    v2 += 1
+                    while v2 != 8
+                again
+                exit
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0x40, 0x01,
+            0x61, 0x02,
+            0xE0, 0x9E,
+            0x12, 0x0E,
+            0x61, 0x03,
+            0x12, 0x10,
+            0x61, 0x04,
+            0x72, 0x01,
+            0x42, 0x08,
+            0x12, 0x18,
+            0x12, 0x10,
+            0x00, 0xFD,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_comparisons() {
+        let program = compile("
+            : main
+                if v1 < 5 then v2 := 1
+                if v1 > v3 then v2 := 1
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0x6F, 0x05,
+            0x8F, 0x17,
+            0x4F, 0x00,
+            0x62, 0x01,
+            0x8F, 0x30,
+            0x8F, 0x15,
+            0x4F, 0x00,
+            0x62, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_macros_and_calc() {
+        let program = compile("
+            :macro add-twice reg amount { reg += amount reg += amount }
+            :calc WIDTH { 2 * 3 + 1 }
+            :calc HALF { ( WIDTH + 1 ) / 2 }
+            : main
+                add-twice v3 WIDTH
+                v0 := HALF
+                :byte { HERE - 0x200 }
+        ").unwrap();
+
+        assert_eq!(program, vec![
+            0x12, 0x02,
+            0x73, 0x08,
+            0x73, 0x08,
+            0x60, 0x04,
+            0x08,
+        ]);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(error_message(": main\n  jump nowhere"), "<source>:2: Undefined name `nowhere`");
+        assert_eq!(error_message(": main\n  v0 := 256"), "<source>:2: 256 does not fit in a byte");
+        assert_eq!(error_message(": main\n: main"), "<source>:2: `main` is already defined");
+        assert_eq!(error_message(": main\n  loop\n  v0 += 1"), "<source>:2: `loop` without a matching `again`");
+        assert_eq!(error_message(": main\n  again"), "<source>:2: `again` without a matching `loop`");
+        assert_eq!(error_message(": main\n  if v0 == 1 v1 := 2"), "<source>:2: Expected `then` or `begin` but found `v1`");
+        assert_eq!(error_message(": main\n  v0 *= v1"), "<source>:2: Expected an assignment but found `*=`");
+        assert_eq!(error_message(": main\n  plane 4"), "<source>:2: 4 does not fit in a plane mask");
+        assert_eq!(error_message("v0 := 1"), "<source>:1: Program has no `main` label");
+        assert_eq!(error_message(":macro m { m }\n: main m"), "<source>:2: Too many macro expansions; does `m` invoke itself?");
+        assert_eq!(error_message(": main\n  native 0x0EE"), "<source>:2: 0x0ee is not a SYS address; it decodes as RET");
+        assert_eq!(error_message(": main\n  :org 0x200 0x11"), "<source>:2: 512 does not fit in the program area");
+        assert_eq!(error_message(":org 0x1000\n: main exit"), "<source>:2: 4096 does not fit in an address");
+        assert_eq!(error_message(": main\n  :org 0x1000\n  loop\n  again"), "<source>:4: 4096 does not fit in an address");
+        assert_eq!(error_message(": main\n  :org 0xFFC\n  loop\n  while v0 == 1\n  again"), "<source>:5: 4098 does not fit in an address");
+    }
+}
@@ -0,0 +1,344 @@
+//! Keeps recent machine states so a run can be stepped backwards.
+//!
+//! The newest snapshot is kept whole. Each older one is stored as the
+//! difference from the snapshot after it, run-length encoded, so frames that
+//! change little cost little and the oldest can be dropped at any time.
+
+use crate::cpu::{ CPU, StateError };
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RewindError {
+    /// Fewer than `steps` older snapshots are buffered.
+    NotBuffered { steps: usize, available: usize },
+    State(StateError),
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewindError::NotBuffered { steps, available } =>
+                write!(f, "Cannot rewind {} snapshots, only {} are buffered", steps, available),
+            RewindError::State(err) => write!(f, "Could not restore snapshot: {}", err),
+        }
+    }
+}
+
+impl Error for RewindError {}
+
+impl From<StateError> for RewindError {
+    fn from(err: StateError) -> Self {
+        RewindError::State(err)
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+// Encodes `old` relative to `new` as the length of `old` followed by
+// (unchanged run, changed run, XORed bytes) triples.
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| old[i] ^ new.get(i).copied().unwrap_or(0);
+    let mut delta = Vec::new();
+    push_varint(&mut delta, old.len());
+
+    let mut i = 0;
+    while i < old.len() {
+        let start = i;
+        while i < old.len() && xor(i) == 0 {
+            i += 1;
+        }
+        let same = i - start;
+
+        let changed_start = i;
+        while i < old.len() && xor(i) != 0 {
+            i += 1;
+        }
+
+        push_varint(&mut delta, same);
+        push_varint(&mut delta, i - changed_start);
+        delta.extend((changed_start..i).map(xor));
+    }
+
+    delta
+}
+
+fn apply_delta(new: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut old: Vec<u8> = (0..len).map(|i| new.get(i).copied().unwrap_or(0)).collect();
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for byte in old[i..i + changed].iter_mut() {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += changed;
+    }
+
+    old
+}
+
+/// A ring buffer of snapshots taken every `interval` frames, holding as many
+/// as fit in the memory budget. The newest snapshot is always kept, even if
+/// it alone is over budget.
+pub struct RewindBuffer {
+    interval: u32,
+    budget: usize,
+    frame: u32,
+    latest: Option<Vec<u8>>,
+    // Oldest first; each entry rebuilds its snapshot from the one after it.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    /// Panics if `interval` is 0.
+    pub fn new(interval: u32, budget: usize) -> Self {
+        assert!(interval > 0, "Rewind interval must be at least one frame");
+
+        RewindBuffer { interval, budget, frame: 0, latest: None, deltas: VecDeque::new(), delta_bytes: 0 }
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the memory budget, dropping the oldest snapshots if needed.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    /// Number of snapshots buffered.
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes held by the buffered snapshots.
+    pub fn memory_used(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    /// Call once per frame. Takes a snapshot on the first call and every
+    /// `interval` frames after that.
+    pub fn record(&mut self, cpu: &CPU) {
+        if self.frame == 0 {
+            self.push(cpu.save_state());
+        }
+        self.frame = (self.frame + 1) % self.interval;
+    }
+
+    fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&state, &previous);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+
+        self.latest = Some(state);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.memory_used() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => { self.delta_bytes -= delta.len(); },
+                None => break,
+            }
+        }
+    }
+
+    /// Restores the snapshot `steps` before the newest, 0 being the newest
+    /// itself. Newer snapshots are discarded so recording carries on from
+    /// the restored point. If the CPU rejects the snapshot, the buffer is
+    /// left as it was.
+    pub fn rewind(&mut self, cpu: &mut CPU, steps: usize) -> Result<(), RewindError> {
+        if steps >= self.len() {
+            return Err(RewindError::NotBuffered { steps, available: self.len().saturating_sub(1) });
+        }
+
+        let mut state = self.latest.clone().unwrap();
+        for delta in self.deltas.iter().rev().take(steps) {
+            state = apply_delta(&state, delta);
+        }
+
+        cpu.load_state(&state)?;
+
+        for _ in 0..steps {
+            let delta = self.deltas.pop_back().unwrap();
+            self.delta_bytes -= delta.len();
+        }
+        self.latest = Some(state);
+        self.frame = 1 % self.interval;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frame = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::XoChip;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let new = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let old = vec![1, 2, 9, 4, 5, 6, 0, 0, 7];
+
+        let delta = encode_delta(&new, &old);
+        assert_eq!(apply_delta(&new, &delta), old);
+        assert_eq!(apply_delta(&old, &encode_delta(&old, &new)), new);
+        assert_eq!(encode_delta(&new, &new).len(), 3);
+    }
+
+    #[test]
+    fn test_rewind_and_resume() {
+        let mut chip8 = CPU::default();
+        let mut buffer = RewindBuffer::new(2, 1 << 20);
+        let mut states = Vec::new();
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 1
+            JP loop
+        ");
+
+        for _ in 0..10 {
+            states.push(chip8.save_state());
+            buffer.record(&chip8);
+            chip8.run_cycles(2).unwrap();
+        }
+        assert_eq!(buffer.len(), 5);
+
+        // Snapshots were taken on frames 0, 2, 4, 6 and 8.
+        buffer.rewind(&mut chip8, 2).unwrap();
+        assert_eq!(chip8.save_state(), states[4]);
+        assert_eq!(buffer.len(), 3);
+
+        chip8.run_cycles(2).unwrap();
+        assert_eq!(chip8.save_state(), states[5]);
+
+        buffer.rewind(&mut chip8, 0).unwrap();
+        assert_eq!(chip8.save_state(), states[4]);
+        assert_eq!(buffer.rewind(&mut chip8, 3), Err(RewindError::NotBuffered { steps: 3, available: 2 }));
+    }
+
+    #[test]
+    fn test_failed_rewind_keeps_buffer() {
+        let mut chip8 = CPU::default();
+        let mut buffer = RewindBuffer::new(1, 1 << 20);
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 1
+            JP loop
+        ");
+
+        let first = chip8.save_state();
+        for _ in 0..3 {
+            buffer.record(&chip8);
+            chip8.run_cycles(2).unwrap();
+        }
+
+        let mut other = CPU::with_variant(&XoChip);
+        match buffer.rewind(&mut other, 2) {
+            Err(RewindError::State(StateError::VariantMismatch { .. })) => {},
+            other => panic!("Expected a variant mismatch, got {:?}", other),
+        }
+        assert_eq!(buffer.len(), 3);
+
+        buffer.rewind(&mut chip8, 2).unwrap();
+        assert_eq!(chip8.save_state(), first);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_deltas_are_compact() {
+        let mut chip8 = CPU::default();
+        let mut buffer = RewindBuffer::new(1, 1 << 20);
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 1
+            JP loop
+        ");
+
+        for _ in 0..10 {
+            buffer.record(&chip8);
+            chip8.run_cycles(1).unwrap();
+        }
+
+        let full = chip8.save_state().len();
+        assert!(buffer.memory_used() < full + 9 * 32, "{} bytes", buffer.memory_used());
+    }
+
+    #[test]
+    fn test_budget_drops_oldest() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+        loop:
+            ADD V0, 1
+            JP loop
+        ");
+
+        let full = chip8.save_state().len();
+        let mut buffer = RewindBuffer::new(1, full + 40);
+        let mut states = Vec::new();
+
+        for _ in 0..20 {
+            states.push(chip8.save_state());
+            buffer.record(&chip8);
+            chip8.run_cycles(2).unwrap();
+        }
+        assert!(buffer.memory_used() <= full + 40);
+        assert!(buffer.len() > 1 && buffer.len() < 20);
+
+        let oldest = buffer.len() - 1;
+        buffer.rewind(&mut chip8, oldest).unwrap();
+        assert_eq!(chip8.save_state(), states[19 - oldest]);
+
+        buffer.set_budget(0);
+        assert_eq!(buffer.len(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+//! Renders the buzzer to 16-bit mono PCM, one 60 Hz tick at a time, and
+//! writes the result out as a WAV file.
+
+use crate::cpu::{ CPU, TIMER_FREQUENCY };
+
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// Frequency of the square wave played while no XO-CHIP pattern is loaded.
+pub const BEEP_FREQUENCY: f64 = 440.0;
+
+const AMPLITUDE: i16 = 8_000;
+const PATTERN_BITS: f64 = 128.0;
+
+/// Sample rate of an XO-CHIP audio pattern, in bits per second.
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+/// Turns the state of the sound timer into samples. While the timer runs it
+/// plays the loaded XO-CHIP pattern, or a square wave if there is none.
+pub struct AudioGenerator {
+    sample_rate: u32,
+    samples: Vec<i16>,
+    // Position within the current waveform, in cycles for the square wave
+    // and in bits for a pattern.
+    phase: f64,
+    ticks: u64,
+}
+
+impl AudioGenerator {
+    pub fn new(sample_rate: u32) -> Self {
+        AudioGenerator { sample_rate, samples: Vec::new(), phase: 0.0, ticks: 0 }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Renders one 60 Hz tick of audio for the frame the machine has just
+    /// finished, so call it after `run_frame`.
+    pub fn tick(&mut self, cpu: &CPU) {
+        // Spread the remainder so a second is always exactly sample_rate long.
+        let start = self.ticks * u64::from(self.sample_rate) / u64::from(TIMER_FREQUENCY);
+        let end = (self.ticks + 1) * u64::from(self.sample_rate) / u64::from(TIMER_FREQUENCY);
+        self.ticks += 1;
+
+        for _ in start..end {
+            let sample = if !cpu.buzzer_sounded() {
+                0
+            } else if cpu.has_audio_pattern() {
+                let bit = self.phase as usize % PATTERN_BITS as usize;
+                self.phase = (self.phase + pattern_rate(cpu.pitch()) / self.sample_rate as f64) % PATTERN_BITS;
+                if cpu.audio_pattern()[bit / 8] & (0x80 >> (bit % 8)) != 0 { AMPLITUDE } else { -AMPLITUDE }
+            } else {
+                let high = self.phase < 0.5;
+                self.phase = (self.phase + BEEP_FREQUENCY / self.sample_rate as f64) % 1.0;
+                if high { AMPLITUDE } else { -AMPLITUDE }
+            };
+
+            self.samples.push(sample);
+        }
+    }
+
+    pub fn write_wav<W: Write>(&self, out: W) -> io::Result<()> {
+        write_wav(out, &self.samples, self.sample_rate)
+    }
+
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_wav(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Default for AudioGenerator {
+    fn default() -> Self {
+        AudioGenerator::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+/// Writes 16-bit mono PCM samples as a WAV file.
+pub fn write_wav<W: Write>(mut out: W, samples: &[i16], sample_rate: u32) -> io::Result<()> {
+    let data_size = (samples.len() * 2) as u32;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sound_timer_drives_square_wave() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 3
+            LD ST, V0
+            EXIT
+        ");
+
+        chip8.run().unwrap();
+        let mut generator = AudioGenerator::new(6000);
+
+        for _ in 0..5 {
+            chip8.tick_timers();
+            generator.tick(&chip8);
+        }
+
+        let samples = generator.samples();
+        assert_eq!(samples.len(), 500);
+        assert!(samples[..300].iter().all(|&s| s == AMPLITUDE || s == -AMPLITUDE));
+        assert!(samples[300..].iter().all(|&s| s == 0));
+
+        // 440 Hz at 6000 samples a second is high for just under 7 samples.
+        assert!(samples[..6].iter().all(|&s| s == AMPLITUDE));
+        assert_eq!(samples[7], -AMPLITUDE);
+    }
+
+    #[test]
+    fn test_xo_pattern_playback() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, pattern
+            AUDIO
+            LD V0, 1
+            LD ST, V0
+            EXIT
+        pattern:
+            DB 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        ");
+
+        chip8.run().unwrap();
+        let mut generator = AudioGenerator::new(4000);
+
+        chip8.tick_timers();
+        generator.tick(&chip8);
+
+        // The default pitch plays one bit per sample at 4000 Hz.
+        let samples = generator.samples();
+        assert_eq!(samples.len(), 66);
+        assert_eq!(&samples[..5], &[AMPLITUDE, AMPLITUDE, AMPLITUDE, AMPLITUDE, -AMPLITUDE]);
+    }
+
+    #[test]
+    fn test_sound_timer_of_one_plays_a_frame() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD V0, 1
+            LD ST, V0
+            EXIT
+        ");
+
+        let mut generator = AudioGenerator::new(6000);
+
+        for _ in 0..2 {
+            chip8.run_frame().unwrap();
+            generator.tick(&chip8);
+        }
+
+        let samples = generator.samples();
+        assert_eq!(samples.len(), 200);
+        assert!(samples[..100].iter().all(|&s| s != 0));
+        assert!(samples[100..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_tick_lengths_add_up() {
+        let chip8 = CPU::default();
+        let mut generator = AudioGenerator::default();
+
+        for _ in 0..60 {
+            generator.tick(&chip8);
+        }
+        assert_eq!(generator.samples().len(), DEFAULT_SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn test_pattern_rate() {
+        assert_eq!(pattern_rate(64), 4000.0);
+        assert_eq!(pattern_rate(112), 8000.0);
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let mut out = Vec::new();
+        write_wav(&mut out, &[1, -2], 8000).unwrap();
+
+        assert_eq!(out.len(), 48);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(&out[4..8], &40u32.to_le_bytes());
+        assert_eq!(&out[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&out[40..44], &4u32.to_le_bytes());
+        assert_eq!(&out[44..], &[0x01, 0x00, 0xFE, 0xFF]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Plays programs in a text terminal: the display is drawn with Unicode
+//! half-block characters, two pixel rows per line, and the keyboard stands
+//! in for the hex keypad.
+
+use crate::cpu::{ CPU, Framebuffer };
+
+use std::io::{ self, Read, Write };
+use std::mem;
+
+/// The usual QWERTY layout of the COSMAC VIP keypad:
+///
+/// ```text
+/// 1 2 3 4        1 2 3 C
+/// q w e r   ->   4 5 6 D
+/// a s d f        7 8 9 E
+/// z x c v        A 0 B F
+/// ```
+pub const KEY_LAYOUT: [(u8, u8); 16] = [
+    (b'1', 0x1), (b'2', 0x2), (b'3', 0x3), (b'4', 0xC),
+    (b'q', 0x4), (b'w', 0x5), (b'e', 0x6), (b'r', 0xD),
+    (b'a', 0x7), (b's', 0x8), (b'd', 0x9), (b'f', 0xE),
+    (b'z', 0xA), (b'x', 0x0), (b'c', 0xB), (b'v', 0xF),
+];
+
+/// Terminals only report key presses, so a key counts as held for this many
+/// frames after its last press. Auto-repeat keeps a held key down.
+pub const KEY_HOLD_FRAMES: u32 = 6;
+
+const ESCAPE: u8 = 0x1B;
+const CTRL_C: u8 = 0x03;
+
+/// Maps a typed character to a keypad key, ignoring case.
+pub fn keypad_key(c: u8) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    KEY_LAYOUT.iter().find(|(typed, _)| *typed == c).map(|(_, key)| *key)
+}
+
+/// Renders pixel rows `2 * line` and `2 * line + 1` as one line of text.
+pub fn render_line(framebuffer: &Framebuffer, line: usize) -> String {
+    let top = line * 2;
+    let bottom = top + 1;
+
+    (0..framebuffer.width())
+        .map(|x| {
+            let upper = framebuffer.is_lit(x, top);
+            let lower = bottom < framebuffer.height() && framebuffer.is_lit(x, bottom);
+            match (upper, lower) {
+                (false, false) => ' ',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (true, true) => '\u{2588}',
+            }
+        })
+        .collect()
+}
+
+/// Renders the whole display, one line per pair of pixel rows.
+pub fn render(framebuffer: &Framebuffer) -> String {
+    (0..framebuffer.height().div_ceil(2))
+        .map(|line| render_line(framebuffer, line) + "\n")
+        .collect()
+}
+
+/// Keys pressed recently enough to still count as held.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeldKeys {
+    frames_left: [u32; 16],
+}
+
+impl HeldKeys {
+    pub fn new() -> Self {
+        HeldKeys::default()
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.frames_left[(key & 0x0F) as usize] = KEY_HOLD_FRAMES;
+    }
+
+    /// Advances one frame and returns the keys that are no longer held.
+    pub fn tick(&mut self) -> Vec<u8> {
+        let mut released = Vec::new();
+
+        for (key, frames) in self.frames_left.iter_mut().enumerate() {
+            if *frames == 1 {
+                released.push(key as u8);
+            }
+            *frames = frames.saturating_sub(1);
+        }
+
+        released
+    }
+}
+
+/// Puts the terminal into raw, non-blocking mode on the alternate screen for
+/// as long as it is alive.
+pub struct Terminal {
+    original: libc::termios,
+    held: HeldKeys,
+    drawn_size: Option<(usize, usize)>,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Terminal> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Reads return straight away with whatever has been typed.
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+
+        Ok(Terminal { original, held: HeldKeys::new(), drawn_size: None })
+    }
+
+    /// Applies everything typed since the last frame to the keypad. Returns
+    /// false once Esc or Ctrl-C has been pressed.
+    pub fn poll_input(&mut self, cpu: &mut CPU) -> io::Result<bool> {
+        for key in self.held.tick() {
+            cpu.release_key(key);
+        }
+
+        let mut buffer = [0; 64];
+        let read = io::stdin().read(&mut buffer)?;
+
+        for &c in buffer[..read].iter() {
+            if c == ESCAPE || c == CTRL_C {
+                return Ok(false);
+            }
+
+            if let Some(key) = keypad_key(c) {
+                self.held.press(key);
+                cpu.press_key(key);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Redraws the lines covering the rows that changed since the last call,
+    /// or everything after a change of resolution.
+    pub fn draw(&mut self, cpu: &mut CPU) -> io::Result<()> {
+        let framebuffer = cpu.framebuffer();
+        let size = (framebuffer.width(), framebuffer.height());
+
+        let mut lines: Vec<usize> = cpu.dirty_rows().iter().map(|row| row / 2).collect();
+        if self.drawn_size != Some(size) {
+            print!("\x1b[2J");
+            lines = (0..size.1.div_ceil(2)).collect();
+        }
+        lines.dedup();
+
+        let mut out = String::new();
+        for line in lines {
+            out += &format!("\x1b[{};1H{}", line + 1, render_line(&framebuffer, line));
+        }
+        print!("{}", out);
+        io::stdout().flush()?;
+
+        self.drawn_size = Some(size);
+        cpu.clear_dirty();
+        Ok(())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypad_key() {
+        assert_eq!(keypad_key(b'1'), Some(0x1));
+        assert_eq!(keypad_key(b'x'), Some(0x0));
+        assert_eq!(keypad_key(b'V'), Some(0xF));
+        assert_eq!(keypad_key(b'p'), None);
+
+        let mut keys: Vec<u8> = KEY_LAYOUT.iter().map(|(_, key)| *key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_render_half_blocks() {
+        let mut chip8 = CPU::default();
+
+        chip8.load_asm("
+            LD I, sprite
+            DRW V0, V0, 3
+            EXIT
+        sprite:
+            DB 0b10100000, 0b01100000, 0b10000000
+        ");
+
+        chip8.run().unwrap();
+        let text = render(&chip8.framebuffer());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0].chars().count(), 64);
+        assert!(lines[0].starts_with("\u{2580}\u{2584}\u{2588} "));
+        assert!(lines[1].starts_with("\u{2580} "));
+        assert!(lines[2].chars().all(|c| c == ' '));
+    }
+
+    #[test]
+    fn test_held_keys_release() {
+        let mut held = HeldKeys::new();
+
+        held.press(0xA);
+        for _ in 1..KEY_HOLD_FRAMES {
+            assert!(held.tick().is_empty());
+        }
+        assert_eq!(held.tick(), vec![0xA]);
+        assert!(held.tick().is_empty());
+    }
+}